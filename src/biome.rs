@@ -0,0 +1,225 @@
+//! Biome classification
+//!
+//! Biomes are looked up in a Whittaker-style table from the temperature and moisture of a cell,
+//! both sampled from their own noise layer. Temperature decreases with altitude and,
+//! on a [`Planet`](crate::planet::Planet), towards the poles.
+//! When set, biomes replace the gradient when coloring a [`Map`](crate::map::Map),
+//! a [`Terrain`](crate::terrain::Terrain) or a [`Planet`](crate::planet::Planet)
+use bevy::math::Vec3;
+use noise::NoiseFn;
+use serde::{Deserialize, Serialize};
+
+use crate::noise::{build_graph, generate_noise_grid, Function, Graph, Method};
+
+/// Biome with the color it is rendered with
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Biome {
+    /// Name of the biome
+    pub name: String,
+    /// Color representing the biome
+    pub color: [u8; 4],
+}
+
+impl Default for Biome {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            color: [0, 0, 0, 255],
+        }
+    }
+}
+
+/// Noise layer with values in the range [0, 1], such as moisture or temperature
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BiomeLayer {
+    /// Seed of the noise
+    pub seed: u32,
+    /// Scale of the noise
+    pub scale: f64,
+    /// Offset of the noise
+    pub offset: [f64; 3],
+    /// Method used to generate noise
+    pub method: Method,
+    /// Function used to generate noise
+    pub function: Function,
+}
+
+impl Default for BiomeLayer {
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            scale: 100.0,
+            offset: [0.0; 3],
+            method: Method::Perlin,
+            function: Function::default(),
+        }
+    }
+}
+
+/// Biome configuration
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Biomes {
+    /// Available biomes, biome IDs are indices in this vector
+    pub biomes: Vec<Biome>,
+    /// Biome IDs by temperature band, from cold to hot, then by moisture band, from dry to wet
+    pub table: Vec<Vec<usize>>,
+    /// Moisture noise layer
+    pub moisture: BiomeLayer,
+    /// Temperature noise layer
+    pub temperature: BiomeLayer,
+    /// Biome ID of cells under sea
+    pub sea_biome: usize,
+    /// Temperature lost from the sea level to the highest altitude
+    pub altitude_cooling: f64,
+    /// Temperature lost from the equator to the poles, only used by planets
+    pub latitude_cooling: f64,
+}
+
+impl Default for Biomes {
+    fn default() -> Self {
+        let biome = |name: &str, color: [u8; 4]| Biome {
+            name: name.to_string(),
+            color,
+        };
+        Self {
+            biomes: vec![
+                biome("Ocean", [40, 80, 170, 255]),
+                biome("Snow", [240, 245, 250, 255]),
+                biome("Tundra", [150, 160, 140, 255]),
+                biome("Taiga", [60, 100, 70, 255]),
+                biome("Grassland", [150, 180, 90, 255]),
+                biome("Temperate forest", [70, 130, 60, 255]),
+                biome("Temperate rainforest", [40, 110, 70, 255]),
+                biome("Desert", [220, 200, 140, 255]),
+                biome("Savanna", [180, 170, 90, 255]),
+                biome("Tropical rainforest", [30, 100, 40, 255]),
+            ],
+            table: vec![vec![2, 2, 1], vec![4, 3, 3], vec![4, 5, 6], vec![7, 8, 9]],
+            moisture: BiomeLayer::default(),
+            temperature: BiomeLayer {
+                seed: 2,
+                ..Default::default()
+            },
+            sea_biome: 0,
+            altitude_cooling: 0.5,
+            latitude_cooling: 0.8,
+        }
+    }
+}
+
+impl Biomes {
+    /// Biome ID of a cell at `height` in percent, with `moisture` and `temperature` in the range [0, 1].
+    /// Cells below `sea_level` in percent are under sea, usually the `sea_percent` of the surface.
+    /// Temperature is cooled by altitude above the sea level before the lookup
+    #[must_use]
+    pub fn classify(&self, height: f64, sea_level: f64, moisture: f64, temperature: f64) -> usize {
+        if height < sea_level {
+            return self.sea_biome;
+        }
+        let altitude = (height - sea_level) / (100.0 - sea_level).max(f64::EPSILON);
+        let temperature = self.altitude_cooling.mul_add(-altitude, temperature);
+        let band = |value: f64, bands: usize| {
+            ((value.clamp(0.0, 1.0) * bands as f64) as usize).min(bands.saturating_sub(1))
+        };
+        let Some(row) = self
+            .table
+            .get(band(temperature, self.table.len()))
+            .filter(|row| !row.is_empty())
+        else {
+            return self.sea_biome;
+        };
+        row[band(moisture, row.len())]
+    }
+
+    /// Color of the biome `id`, transparent if there is no such biome
+    #[must_use]
+    pub fn color(&self, id: usize) -> [u8; 4] {
+        self.biomes.get(id).map_or([0; 4], |biome| biome.color)
+    }
+}
+
+/// Moisture and temperature layers of [`Biomes`], built once for sampling many cells
+pub(crate) struct BiomeSampler<'a> {
+    biomes: &'a Biomes,
+    sea_level: f64,
+    moisture: Graph,
+    temperature: Graph,
+}
+
+impl<'a> BiomeSampler<'a> {
+    /// Samples `biomes` with cells below `sea_level` in percent under sea
+    pub(crate) fn new(biomes: &'a Biomes, sea_level: f64) -> Self {
+        let graph = |layer: &BiomeLayer| {
+            build_graph(&layer.method, &layer.function, None, None, layer.seed)
+        };
+        Self {
+            biomes,
+            sea_level,
+            moisture: graph(&biomes.moisture),
+            temperature: graph(&biomes.temperature),
+        }
+    }
+
+    /// Biome IDs of a grid of noise values, laid out like [`generate_noise_grid`]
+    pub(crate) fn grid(
+        &self,
+        noise_values: &[Vec<f64>],
+        origin: [i64; 2],
+        step: u32,
+    ) -> Vec<Vec<usize>> {
+        let size = [
+            noise_values.len() as u32,
+            noise_values.first().map_or(0, Vec::len) as u32,
+        ];
+        let layer = |graph: &Graph, layer: &BiomeLayer| {
+            let offset = [layer.offset[0], layer.offset[1]];
+            generate_noise_grid(graph, origin, size, step, layer.scale, offset)
+        };
+        let moisture = layer(&self.moisture, &self.biomes.moisture);
+        let temperature = layer(&self.temperature, &self.biomes.temperature);
+        noise_values
+            .iter()
+            .zip(moisture.iter().zip(&temperature))
+            .map(|(heights, (moisture, temperature))| {
+                heights
+                    .iter()
+                    .zip(moisture.iter().zip(temperature))
+                    .map(|(&height, (&moisture, &temperature))| {
+                        self.biomes.classify(
+                            height,
+                            self.sea_level,
+                            moisture / 100.0,
+                            temperature / 100.0,
+                        )
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Biome ID at `direction` on a planet, at `height` in percent.
+    /// Temperature is cooled towards the poles
+    pub(crate) fn at_direction(&self, height: f64, direction: Vec3) -> usize {
+        let sample = |graph: &Graph, layer: &BiomeLayer| {
+            let point = [direction.x, direction.y, direction.z].map(f64::from);
+            let offset = layer.offset;
+            let scale = layer.scale / 100.0;
+            let [x, y, z] = [0, 1, 2].map(|axis| point[axis] / scale + offset[axis]);
+            f64::midpoint(graph.get([x, y, z]).clamp(-1.0, 1.0), 1.0)
+        };
+        let latitude = f64::from(direction.normalize_or_zero().y.abs());
+        let temperature = self.biomes.latitude_cooling.mul_add(
+            -latitude,
+            sample(&self.temperature, &self.biomes.temperature),
+        );
+        self.biomes.classify(
+            height,
+            self.sea_level,
+            sample(&self.moisture, &self.biomes.moisture),
+            temperature,
+        )
+    }
+}
@@ -0,0 +1,365 @@
+//! Erosion of heightmaps
+//!
+//! Erosion runs on the noise values of a [`Map`](crate::map::Map) or a
+//! [`Terrain`](crate::terrain::Terrain), before they are colored or meshed.
+//! Results only depend on the configuration, so the same seed always erodes the same way.
+//! Tileable noise is eroded across its edges, so that it still tiles
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+
+/// Particle based hydraulic erosion.
+/// Droplets of water run downhill, eroding the heightmap where they speed up
+/// and depositing sediment where they slow down, carving valleys and drainage
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HydraulicErosion {
+    /// Seed of the droplet positions
+    pub seed: u32,
+    /// Number of simulated droplets
+    pub droplets: u32,
+    /// Maximum number of steps of a droplet
+    pub lifetime: u32,
+    /// How much a droplet keeps its direction instead of following the slope, between 0 and 1
+    pub inertia: f32,
+    /// Multiplies the amount of sediment a droplet can carry
+    pub capacity: f32,
+    /// Fraction of the excess sediment deposited each step, between 0 and 1
+    pub deposition: f32,
+    /// Fraction of the free capacity eroded each step, between 0 and 1
+    pub erosion: f32,
+    /// Fraction of the water evaporating each step, between 0 and 1
+    pub evaporation: f32,
+    /// Radius in cells around a droplet that is eroded
+    pub radius: u32,
+}
+
+impl Default for HydraulicErosion {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            droplets: 20000,
+            lifetime: 30,
+            inertia: 0.05,
+            capacity: 4.0,
+            deposition: 0.3,
+            erosion: 0.3,
+            evaporation: 0.01,
+            radius: 3,
+        }
+    }
+}
+
+/// Heights in the range [0, 1] of a grid of noise values, repeating in both axes if `wrap` is set
+struct HeightGrid {
+    heights: Vec<f32>,
+    width: usize,
+    depth: usize,
+    wrap: bool,
+}
+
+impl HeightGrid {
+    fn new(noise_values: &[Vec<f64>], wrap: bool) -> Self {
+        Self {
+            heights: noise_values
+                .iter()
+                .flatten()
+                .map(|&value| value as f32 / 100.0)
+                .collect(),
+            width: noise_values.len(),
+            depth: noise_values.first().map_or(0, Vec::len),
+            wrap,
+        }
+    }
+
+    fn write(&self, noise_values: &mut [Vec<f64>]) {
+        for (row, heights) in noise_values
+            .iter_mut()
+            .zip(self.heights.chunks_exact(self.depth))
+        {
+            for (value, &height) in row.iter_mut().zip(heights) {
+                *value = f64::from(height) * 100.0;
+            }
+        }
+    }
+
+    const fn index(&self, x: usize, y: usize) -> usize {
+        x * self.depth + y
+    }
+
+    /// Index of the cell at `x`, `y`, or `None` outside the grid unless it wraps
+    fn wrapped_index(&self, x: i64, y: i64) -> Option<usize> {
+        let [width, depth] = [self.width, self.depth].map(|size| size as i64);
+        let (x, y) = if self.wrap {
+            (x.rem_euclid(width), y.rem_euclid(depth))
+        } else {
+            (x, y)
+        };
+        (x >= 0 && y >= 0 && x < width && y < depth).then(|| self.index(x as usize, y as usize))
+    }
+
+    /// Indices of the cells at the corners of the cell `[x, y]`, in the order
+    /// `(x, y)`, `(x, y + 1)`, `(x + 1, y)`, `(x + 1, y + 1)`
+    fn corners(&self, [x, y]: [usize; 2]) -> [usize; 4] {
+        let next = |value: usize, size: usize| {
+            if self.wrap {
+                (value + 1) % size
+            } else {
+                value + 1
+            }
+        };
+        let (next_x, next_y) = (next(x, self.width), next(y, self.depth));
+        [
+            self.index(x, y),
+            self.index(x, next_y),
+            self.index(next_x, y),
+            self.index(next_x, next_y),
+        ]
+    }
+
+    /// `position` moved back inside the grid if it wraps
+    fn wrap(&self, position: [f32; 2]) -> [f32; 2] {
+        if !self.wrap {
+            return position;
+        }
+        [
+            (position[0], self.width as f32),
+            (position[1], self.depth as f32),
+        ]
+        .map(|(value, size)| {
+            let value = value.rem_euclid(size);
+            // Rounding can land exactly on the size for tiny negative values
+            if value < size {
+                value
+            } else {
+                0.0
+            }
+        })
+    }
+
+    /// Bilinearly interpolated height and gradient at `position`
+    fn sample(&self, [x, y]: [f32; 2]) -> (f32, [f32; 2]) {
+        let (cell_x, cell_y) = (x as usize, y as usize);
+        let (u, v) = (x - cell_x as f32, y - cell_y as f32);
+        let [h00, h01, h10, h11] = self
+            .corners([cell_x, cell_y])
+            .map(|index| self.heights[index]);
+        let gradient = [
+            (h10 - h00).mul_add(1.0 - v, (h11 - h01) * v),
+            (h01 - h00).mul_add(1.0 - u, (h11 - h10) * u),
+        ];
+        let height = h00
+            .mul_add(1.0 - u, h10 * u)
+            .mul_add(1.0 - v, h01.mul_add(1.0 - u, h11 * u) * v);
+        (height, gradient)
+    }
+
+    /// Whether the cell containing `position` lies inside the grid, always true if it wraps
+    fn contains(&self, [x, y]: [f32; 2]) -> bool {
+        self.wrap
+            || x >= 0.0 && y >= 0.0 && x < (self.width - 1) as f32 && y < (self.depth - 1) as f32
+    }
+}
+
+impl HydraulicErosion {
+    const GRAVITY: f32 = 4.0;
+    const MIN_SLOPE: f32 = 0.01;
+
+    /// Erodes a grid of noise values in the range [0, 100].
+    /// If `wrap` is set, droplets flowing out of an edge come back from the opposite one
+    pub(crate) fn erode(&self, noise_values: &mut [Vec<f64>], wrap: bool) {
+        let mut grid = HeightGrid::new(noise_values, wrap);
+        if grid.width < 2 || grid.depth < 2 {
+            return;
+        }
+        // Droplets start in cells with four corners, which every cell has if the grid wraps
+        let extent = [grid.width, grid.depth].map(|size| (size - usize::from(!wrap)) as f32);
+        let brush = self.brush();
+        let mut rng = XorShiftRng::seed_from_u64(u64::from(self.seed));
+        let inertia = self.inertia.clamp(0.0, 1.0);
+
+        for _ in 0..self.droplets {
+            let mut position = [rng.gen_range(0.0..extent[0]), rng.gen_range(0.0..extent[1])];
+            let mut direction = [0.0_f32; 2];
+            let mut speed = 1.0_f32;
+            let mut water = 1.0_f32;
+            let mut sediment = 0.0_f32;
+
+            for _ in 0..self.lifetime {
+                let cell = [position[0] as usize, position[1] as usize];
+                let offset = [position[0] - cell[0] as f32, position[1] - cell[1] as f32];
+                let (height, gradient) = grid.sample(position);
+
+                direction = [0, 1].map(|axis| {
+                    direction[axis].mul_add(inertia, -gradient[axis] * (1.0 - inertia))
+                });
+                let length = direction[0].hypot(direction[1]);
+                if length <= f32::EPSILON {
+                    break;
+                }
+                direction = direction.map(|component| component / length);
+                position = grid.wrap([position[0] + direction[0], position[1] + direction[1]]);
+                if !grid.contains(position) {
+                    break;
+                }
+
+                let height_difference = grid.sample(position).0 - height;
+                let capacity =
+                    (-height_difference).max(Self::MIN_SLOPE) * speed * water * self.capacity;
+
+                if sediment > capacity || height_difference > 0.0 {
+                    // Fill the pit when going uphill, drop the excess sediment otherwise
+                    let deposit = if height_difference > 0.0 {
+                        height_difference.min(sediment)
+                    } else {
+                        (sediment - capacity) * self.deposition
+                    };
+                    sediment -= deposit;
+                    let [i00, i01, i10, i11] = grid.corners(cell);
+                    let [u, v] = offset;
+                    grid.heights[i00] += deposit * (1.0 - u) * (1.0 - v);
+                    grid.heights[i10] += deposit * u * (1.0 - v);
+                    grid.heights[i01] += deposit * (1.0 - u) * v;
+                    grid.heights[i11] += deposit * u * v;
+                } else {
+                    let amount = ((capacity - sediment) * self.erosion).min(-height_difference);
+                    for &(dx, dy, weight) in &brush {
+                        let Some(index) =
+                            grid.wrapped_index(cell[0] as i64 + dx, cell[1] as i64 + dy)
+                        else {
+                            continue;
+                        };
+                        let eroded = (amount * weight).min(grid.heights[index].max(0.0));
+                        grid.heights[index] -= eroded;
+                        sediment += eroded;
+                    }
+                }
+
+                // Droplets speed up going downhill
+                speed = speed
+                    .mul_add(speed, -height_difference * Self::GRAVITY)
+                    .max(0.0)
+                    .sqrt();
+                water *= 1.0 - self.evaporation;
+            }
+        }
+        grid.write(noise_values);
+    }
+
+    /// Offsets around a droplet with their share of the erosion
+    fn brush(&self) -> Vec<(i64, i64, f32)> {
+        let radius = i64::from(self.radius.max(1));
+        let mut brush = vec![];
+        for dx in -radius..=radius {
+            for dy in -radius..=radius {
+                let weight = radius as f32 - (dx as f32).hypot(dy as f32);
+                if weight > 0.0 {
+                    brush.push((dx, dy, weight));
+                }
+            }
+        }
+        let total: f32 = brush.iter().map(|&(_, _, weight)| weight).sum();
+        for (_, _, weight) in &mut brush {
+            *weight /= total;
+        }
+        brush
+    }
+}
+
+/// Thermal erosion, moving material downhill where the slope is steeper than the talus angle,
+/// resulting in scree-like cliffs
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ThermalErosion {
+    /// Number of passes over the heightmap
+    pub iterations: u32,
+    /// Steepest stable slope in degrees, with noise values in percent and cells one unit apart
+    pub talus_angle: f32,
+    /// Fraction of the material above the talus angle moved each pass, between 0 and 1
+    pub strength: f32,
+}
+
+impl Default for ThermalErosion {
+    fn default() -> Self {
+        Self {
+            iterations: 50,
+            talus_angle: 45.0,
+            strength: 0.5,
+        }
+    }
+}
+
+impl ThermalErosion {
+    /// Erodes a grid of noise values in the range [0, 100].
+    /// Every pass computes all transfers before applying them, so the result does not depend
+    /// on the order cells are visited in. If `wrap` is set, cells on opposite edges are neighbors
+    pub(crate) fn erode(&self, noise_values: &mut [Vec<f64>], wrap: bool) {
+        let rows = noise_values.len();
+        let cols = noise_values.first().map_or(0, Vec::len);
+        let talus = f64::from(self.talus_angle.to_radians().tan().max(0.0));
+        let strength = f64::from(self.strength.clamp(0.0, 1.0));
+        let neighbors: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+        let neighbor = |index: usize, offset: isize, size: usize| {
+            if wrap {
+                Some((index + size).checked_add_signed(offset)? % size)
+            } else {
+                index
+                    .checked_add_signed(offset)
+                    .filter(|&index| index < size)
+            }
+        };
+        let mut changes = vec![vec![0.0; cols]; rows];
+
+        for _ in 0..self.iterations {
+            for row in &mut changes {
+                row.fill(0.0);
+            }
+            for x in 0..rows {
+                for y in 0..cols {
+                    let height = noise_values[x][y];
+                    let lower = neighbors.map(|(dx, dy)| {
+                        let (nx, ny) = (neighbor(x, dx, rows)?, neighbor(y, dy, cols)?);
+                        let excess = height - noise_values[nx][ny] - talus;
+                        (excess > 0.0).then_some((nx, ny, excess))
+                    });
+                    let total: f64 = lower.iter().flatten().map(|&(_, _, excess)| excess).sum();
+                    let Some(steepest) = lower
+                        .iter()
+                        .flatten()
+                        .map(|&(_, _, excess)| excess)
+                        .reduce(f64::max)
+                    else {
+                        continue;
+                    };
+                    // Moving half of the steepest excess levels the steepest pair
+                    let moved = strength * steepest / 2.0;
+                    changes[x][y] -= moved;
+                    for &(nx, ny, excess) in lower.iter().flatten() {
+                        changes[nx][ny] += moved * excess / total;
+                    }
+                }
+            }
+            for (values, changes) in noise_values.iter_mut().zip(&changes) {
+                for (value, change) in values.iter_mut().zip(changes) {
+                    *value += change;
+                }
+            }
+        }
+    }
+}
+
+/// Applies the configured erosion steps to a grid of noise values, hydraulic erosion first.
+/// If `wrap` is set, the grid repeats in both axes, like tileable noise
+pub(crate) fn erode(
+    noise_values: &mut [Vec<f64>],
+    hydraulic: Option<&HydraulicErosion>,
+    thermal: Option<&ThermalErosion>,
+    wrap: bool,
+) {
+    if let Some(erosion) = hydraulic {
+        erosion.erode(noise_values, wrap);
+    }
+    if let Some(erosion) = thermal {
+        erosion.erode(noise_values, wrap);
+    }
+}
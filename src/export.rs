@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+
+/// File format of exported models
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ModelFormat {
+    /// Binary glTF, a single `.glb` file
+    #[default]
+    Glb,
+    /// Standard glTF, a `.gltf` file referencing a `.bin` file with the same name
+    Gltf,
+    /// Standard glTF with the buffer embedded as a data uri, a single `.gltf` file
+    GltfEmbedded,
+    /// Wavefront OBJ, a `.obj` file with vertex colors and a `.mtl` file.
+    /// A baked texture is written as a `.png` file and referenced by the material
+    Obj,
+    /// Binary PLY with vertex colors, a single `.ply` file
+    Ply,
+    /// Binary STL, a single `.stl` file. Colors are not exported and wireframe meshes are not supported
+    Stl,
+}
+
+impl ModelFormat {
+    /// File extension of the main exported file
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Glb => "glb",
+            Self::Gltf | Self::GltfEmbedded => "gltf",
+            Self::Obj => "obj",
+            Self::Ply => "ply",
+            Self::Stl => "stl",
+        }
+    }
+}
+
+/// File format of exported heightmaps.
+/// Noise values from 0 to 100 are mapped to the full range of the format, so heights are
+/// not stretched and maps generated with the same noise share the same scale
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HeightmapFormat {
+    /// 16-bit grayscale PNG
+    #[default]
+    Png16,
+    /// 32-bit floating point grayscale TIFF, with heights in the range [0, 1]
+    Tiff32,
+    /// Headerless 16-bit little endian RAW, row by row, as imported by Unity and Unreal
+    R16,
+}
+
+impl HeightmapFormat {
+    /// File extension of the exported file
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Png16 => "png",
+            Self::Tiff32 => "tiff",
+            Self::R16 => "r16",
+        }
+    }
+
+    /// Mime type of the exported file
+    #[must_use]
+    pub const fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png16 => "image/png",
+            Self::Tiff32 => "image/tiff",
+            Self::R16 => "application/octet-stream",
+        }
+    }
+}
@@ -14,18 +14,57 @@
 #![allow(clippy::similar_names)]
 #![allow(clippy::too_many_lines)]
 #![allow(clippy::default_trait_access)]
+#![allow(clippy::type_complexity)]
 
 //! Procedural generation in Bevy
 
 mod util;
 
+/// Biome classification
+pub mod biome;
+/// Heightmap erosion
+pub mod erosion;
+/// Export formats
+pub mod export;
 /// Map and texture generation
 pub mod map;
 /// Noise configuration
 pub mod noise;
 /// Planet generation
 pub mod planet;
+/// River and lake generation
+pub mod river;
 /// Terrain  generation
 pub mod terrain;
 
 mod tests;
+
+use bevy::prelude::{Component, Vec3};
+
+/// Status of the generation of a [`Map`](map::Map), [`Terrain`](terrain::Terrain)
+/// or [`Planet`](planet::Planet).
+///
+/// Generation runs in the background on the `AsyncComputeTaskPool`.
+/// This component is inserted when generation starts and updated once the result is applied
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq)]
+pub enum GenerationStatus {
+    /// Generation is running in the background
+    #[default]
+    Pending,
+    /// Generated assets have been applied
+    Ready,
+    /// Generation failed with the given error
+    Failed(String),
+}
+
+/// Intersection of a ray with a [`TerrainHeightfield`](terrain::TerrainHeightfield)
+/// or a [`PlanetSampler`](planet::PlanetSampler), in the space the ray was cast in
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RayHit {
+    /// Point where the ray hits the surface
+    pub point: Vec3,
+    /// Normal of the surface at `point`
+    pub normal: Vec3,
+    /// Distance from the origin of the ray to `point`
+    pub distance: f32,
+}
@@ -18,30 +18,39 @@
 //!     commands.spawn(MapBundle::default());
 //! }
 //! ```
-use bevy::{
-    prelude::*,
-    render::{render_asset::RenderAssetUsages, render_resource::TextureFormat},
-};
-use image::{imageops::FilterType, DynamicImage, Pixel};
+use bevy::prelude::*;
+use image::{imageops::FilterType, DynamicImage, Pixel, RgbaImage};
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 use crate::{
-    noise::{generate_noise_map, Noise},
-    util::export_asset,
+    biome::{BiomeSampler, Biomes},
+    erosion::{erode, HydraulicErosion, ThermalErosion},
+    export::HeightmapFormat,
+    noise::{generate_gradient, generate_noise_map, Noise},
+    river::{drain, Drainage, Rivers},
+    util::{
+        export_asset, export_heightmap, image_from_buffer, linear_image_from_buffer, png_bytes,
+        replace_asset, save_bytes, write_bytes, GenerationTask,
+    },
+    GenerationStatus,
 };
 
-/// Plugin to generate map
+/// Plugin to generate map.
+/// The map is only regenerated when its [`Map`] component changes.
+/// Generation runs in the background, see [`GenerationStatus`]
 pub struct MapPlugin;
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, generate_map);
+        app.add_systems(Update, (spawn_map_tasks, apply_map_tasks).chain());
     }
 }
 
 /// Component for map configuration
-#[derive(Component, Serialize, Deserialize)]
+#[derive(Component, Clone, Serialize, Deserialize)]
 #[serde(default, rename_all = "camelCase")]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Map {
     /// Noise configuration of the map
     pub noise: Noise,
@@ -56,6 +65,43 @@ pub struct Map {
     /// If true, exports model in glb format
     #[serde(skip)]
     pub export: bool,
+    /// If set, exports the heightmap in the given format
+    #[serde(skip)]
+    pub export_heightmap: Option<HeightmapFormat>,
+    /// If set, a tangent space normal map is generated alongside the image
+    pub normal_map: Option<NormalMap>,
+    /// If set, the noise values are eroded before coloring
+    pub hydraulic_erosion: Option<HydraulicErosion>,
+    /// If set, the noise values are eroded by slope after hydraulic erosion
+    pub thermal_erosion: Option<ThermalErosion>,
+    /// If set, rivers are carved into the noise values after erosion and painted with lakes
+    pub rivers: Option<Rivers>,
+    /// If set, the map is colored by biome instead of by the gradient
+    pub biomes: Option<Biomes>,
+    /// Percentage of the map that is under sea, where biomes are the sea biome and rivers end
+    pub sea_percent: f32,
+}
+
+/// Normal map configuration.
+/// Normals are computed from the noise map and use the OpenGL convention (green pointing up)
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct NormalMap {
+    /// Image handle of normal map
+    #[serde(skip)]
+    pub image: Handle<Image>,
+    /// Multiplies the height differences between neighboring pixels.
+    /// Higher values result in steeper normals
+    pub strength: f32,
+}
+
+impl Default for NormalMap {
+    fn default() -> Self {
+        Self {
+            image: Handle::default(),
+            strength: 10.0,
+        }
+    }
 }
 
 /// Display `Map` as a ui node
@@ -76,97 +122,286 @@ impl Default for Map {
             same_size: true,
             anti_aliasing: true,
             export: false,
+            export_heightmap: None,
+            normal_map: None,
+            hydraulic_erosion: None,
+            thermal_erosion: None,
+            rivers: None,
+            biomes: None,
+            sea_percent: 40.0,
         }
     }
 }
-fn generate_map(mut images: ResMut<Assets<Image>>, mut query: Query<(&mut Map, &mut UiImage)>) {
-    for (mut map, mut ui_image) in &mut query {
-        map.noise.size = map.size;
-        let noise_values = generate_noise_map(&map.noise);
-        let noise = &mut map.noise;
-
-        let mut colors: Vec<colorgrad::Color> = Vec::with_capacity(noise.regions.len());
-        let mut domain: Vec<f64> = Vec::with_capacity(noise.regions.len());
-        for region in &noise.regions {
-            colors.push(colorgrad::Color {
-                r: f64::from(region.color[0]) / 255.0,
-                g: f64::from(region.color[1]) / 255.0,
-                b: f64::from(region.color[2]) / 255.0,
-                a: f64::from(region.color[3]) / 255.0,
-            });
-            domain.push(region.position);
-        }
-        let mut grad = colorgrad::CustomGradient::new()
-            .colors(&colors)
-            .domain(&domain)
-            .build()
-            .unwrap_or_else(|_| {
-                colorgrad::CustomGradient::new()
-                    .colors(&colors)
-                    .build()
-                    .expect("Gradient generation failed")
-            });
-
-        if noise.gradient.segments != 0 {
-            grad = grad.sharp(noise.gradient.segments, noise.gradient.smoothness);
-        }
 
-        let mut gradient_buffer = image::ImageBuffer::from_pixel(
-            noise.gradient.size[0],
-            noise.gradient.size[1],
-            image::Rgba(noise.base_color),
-        );
-
-        for (x, _, pixel) in gradient_buffer.enumerate_pixels_mut() {
-            let rgba = grad
-                .at(f64::from(x) * 100.0 / f64::from(noise.gradient.size[0]))
-                .to_rgba8();
-            pixel.blend(&image::Rgba(rgba));
-        }
+impl Map {
+    /// Generates the map and encodes it as PNG, without opening a file dialog
+    ///
+    /// # Errors
+    /// Returns an error if generation or encoding fails
+    pub fn to_png_bytes(&self) -> Result<Vec<u8>, String> {
+        let (_, image) = generate_images(self)?;
+        png_bytes(&image)
+    }
 
-        noise.gradient.image = images.add(
-            Image::from_dynamic(
-                gradient_buffer.into(),
-                true,
-                RenderAssetUsages::RENDER_WORLD,
-            )
-            .convert(bevy::render::render_resource::TextureFormat::Rgba8UnormSrgb)
-            .expect("Could not convert to Rgba8UnormSrgb"),
-        );
-
-        let mut image_buffer = image::ImageBuffer::from_pixel(
-            noise.size[0],
-            noise.size[1],
-            image::Rgba(noise.base_color),
-        );
-
-        for (x, y, pixel) in image_buffer.enumerate_pixels_mut() {
-            let height = noise_values[x as usize][y as usize];
-            let target_color = grad.at(height).to_rgba8();
-            pixel.blend(&image::Rgba(target_color));
-        }
-        if !map.same_size {
-            image_buffer = DynamicImage::from(image_buffer.clone())
-                .resize_exact(
-                    map.image_size[0],
-                    map.image_size[1],
-                    if map.anti_aliasing {
-                        FilterType::Triangle
-                    } else {
-                        FilterType::Nearest
-                    },
-                )
-                .to_rgba8();
+    /// Generates the map and writes it as PNG to `path`, without opening a file dialog
+    ///
+    /// # Errors
+    /// Returns an error if generation, encoding or writing the file fails
+    pub fn export_to_path(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        write_bytes(path.as_ref(), &self.to_png_bytes()?)
+    }
+
+    /// Biome ID of every cell of the map, indexed like the noise map.
+    /// Returns `None` if `biomes` is not set
+    #[must_use]
+    pub fn to_biome_ids(&self) -> Option<Vec<Vec<usize>>> {
+        let biomes = self.biomes.as_ref()?;
+        Some(biome_ids(self, biomes, &generate_heightmap(self)))
+    }
+
+    /// Drainage of the map, with its rivers, lakes and flow accumulation.
+    /// Returns `None` if `rivers` is not set
+    #[must_use]
+    pub fn to_drainage(&self) -> Option<Drainage> {
+        generate_drained_heightmap(self).1
+    }
+
+    /// Generates the normal map and encodes it as PNG, without opening a file dialog
+    ///
+    /// # Errors
+    /// Returns an error if `normal_map` is not set or encoding fails
+    pub fn to_normal_map_png_bytes(&self) -> Result<Vec<u8>, String> {
+        let normal_map = self
+            .normal_map
+            .as_ref()
+            .ok_or("Normal map is not enabled")?;
+        let image = generate_normal_map(self, &generate_heightmap(self), normal_map.strength);
+        png_bytes(&image)
+    }
+
+    /// Generates the normal map and writes it as PNG to `path`, without opening a file dialog
+    ///
+    /// # Errors
+    /// Returns an error if `normal_map` is not set, encoding or writing the file fails
+    pub fn export_normal_map_to_path(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        write_bytes(path.as_ref(), &self.to_normal_map_png_bytes()?)
+    }
+
+    /// Encodes the noise map of the map as a heightmap in `format`, without opening a file dialog
+    ///
+    /// # Errors
+    /// Returns an error if encoding fails
+    pub fn to_heightmap_bytes(&self, format: HeightmapFormat) -> Result<Vec<u8>, String> {
+        export_heightmap(&generate_heightmap(self), format)
+    }
+
+    /// Writes the noise map of the map as a heightmap in `format` to `path`, without opening a file dialog
+    ///
+    /// # Errors
+    /// Returns an error if encoding or writing the file fails
+    pub fn export_heightmap_to_path(
+        &self,
+        path: impl AsRef<Path>,
+        format: HeightmapFormat,
+    ) -> Result<(), String> {
+        write_bytes(path.as_ref(), &self.to_heightmap_bytes(format)?)
+    }
+}
+
+#[derive(Component)]
+struct MapTask(GenerationTask<MapOutput>);
+
+struct MapOutput {
+    gradient: RgbaImage,
+    image: RgbaImage,
+    normal_map: Option<RgbaImage>,
+    export: bool,
+    heightmap: Option<(HeightmapFormat, Vec<u8>)>,
+}
+
+fn spawn_map_tasks(mut commands: Commands, mut query: Query<(Entity, &mut Map), Changed<Map>>) {
+    for (entity, mut map) in &mut query {
+        let map = map.bypass_change_detection();
+        let config = map.clone();
+        map.export = false;
+        map.export_heightmap = None;
+        let task = GenerationTask::spawn(move || {
+            let (noise_values, drainage) = generate_drained_heightmap(&config);
+            let heightmap = config
+                .export_heightmap
+                .map(|format| export_heightmap(&noise_values, format).map(|bytes| (format, bytes)))
+                .transpose()?;
+            let (gradient, image) = color_images(&config, &noise_values, drainage.as_ref())?;
+            let normal_map = config
+                .normal_map
+                .as_ref()
+                .map(|normal_map| generate_normal_map(&config, &noise_values, normal_map.strength));
+            Ok(MapOutput {
+                gradient,
+                image,
+                normal_map,
+                export: config.export,
+                heightmap,
+            })
+        });
+        commands
+            .entity(entity)
+            .insert((MapTask(task), GenerationStatus::Pending));
+    }
+}
+
+fn apply_map_tasks(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut query: Query<(Entity, &mut Map, &mut UiImage, &mut MapTask)>,
+) {
+    for (entity, mut map, mut ui_image, mut task) in &mut query {
+        let Some(result) = task.0.poll() else {
+            continue;
+        };
+        let mut entity = commands.entity(entity);
+        entity.remove::<MapTask>();
+        match result {
+            Ok(output) => {
+                let map = map.bypass_change_detection();
+                replace_asset(
+                    &mut images,
+                    &mut map.noise.gradient.image,
+                    image_from_buffer(output.gradient),
+                );
+                if output.export {
+                    export_asset(&output.image, "asset.png");
+                }
+                if let (Some(normal_map), Some(image)) = (&mut map.normal_map, output.normal_map) {
+                    if output.export {
+                        export_asset(&image, "normal_map.png");
+                    }
+                    replace_asset(
+                        &mut images,
+                        &mut normal_map.image,
+                        linear_image_from_buffer(image),
+                    );
+                }
+                replace_asset(
+                    &mut images,
+                    &mut ui_image.texture,
+                    image_from_buffer(output.image),
+                );
+                if let Some((format, heightmap)) = output.heightmap {
+                    save_bytes(
+                        &heightmap,
+                        &format!("heightmap.{}", format.extension()),
+                        format.mime_type(),
+                    );
+                }
+                entity.insert(GenerationStatus::Ready);
+            }
+            Err(error) => {
+                entity.insert(GenerationStatus::Failed(error));
+            }
         }
-        if map.export {
-            export_asset(image_buffer.clone());
-            map.export = false;
+    }
+}
+
+fn generate_heightmap(map: &Map) -> Vec<Vec<f64>> {
+    generate_drained_heightmap(map).0
+}
+
+/// Noise values after erosion and river carving, with the drainage if `rivers` is set
+fn generate_drained_heightmap(map: &Map) -> (Vec<Vec<f64>>, Option<Drainage>) {
+    let mut noise = map.noise.clone();
+    noise.size = map.size;
+    let mut noise_values = generate_noise_map(&noise);
+    erode(
+        &mut noise_values,
+        map.hydraulic_erosion.as_ref(),
+        map.thermal_erosion.as_ref(),
+        noise.tileable,
+    );
+    let drainage = drain(
+        &mut noise_values,
+        map.rivers.as_ref(),
+        f64::from(map.sea_percent),
+        noise.tileable,
+    );
+    (noise_values, drainage)
+}
+
+pub(crate) fn generate_images(map: &Map) -> Result<(RgbaImage, RgbaImage), String> {
+    let (noise_values, drainage) = generate_drained_heightmap(map);
+    color_images(map, &noise_values, drainage.as_ref())
+}
+
+/// Colors the noise values, painting the rivers and lakes of `drainage` over them
+pub(crate) fn color_images(
+    map: &Map,
+    noise_values: &[Vec<f64>],
+    drainage: Option<&Drainage>,
+) -> Result<(RgbaImage, RgbaImage), String> {
+    let noise = &map.noise;
+    let (grad, gradient_buffer) =
+        generate_gradient(&noise.regions, &noise.gradient, noise.base_color)?;
+
+    let mut image_buffer =
+        RgbaImage::from_pixel(map.size[0], map.size[1], image::Rgba(noise.base_color));
+
+    let biomes = map
+        .biomes
+        .as_ref()
+        .map(|biomes| (biomes, biome_ids(map, biomes, noise_values)));
+    for (x, y, pixel) in image_buffer.enumerate_pixels_mut() {
+        let (x, y) = (x as usize, y as usize);
+        let target_color = match &biomes {
+            Some((biomes, ids)) => biomes.color(ids[x][y]),
+            None => grad.at(noise_values[x][y]).to_rgba8(),
+        };
+        pixel.blend(&image::Rgba(target_color));
+        if let (Some(rivers), Some(drainage)) = (&map.rivers, drainage) {
+            if drainage.lakes[x][y] {
+                pixel.blend(&image::Rgba(rivers.lake_color));
+            } else if drainage.rivers[x][y] {
+                pixel.blend(&image::Rgba(rivers.river_color));
+            }
         }
-        let map_texture =
-            Image::from_dynamic(image_buffer.into(), true, RenderAssetUsages::RENDER_WORLD)
-                .convert(TextureFormat::Rgba8UnormSrgb)
-                .expect("Could not convert to Rgba8UnormSrgb");
+    }
+    Ok((gradient_buffer, resize(map, image_buffer)))
+}
+
+fn biome_ids(map: &Map, biomes: &Biomes, noise_values: &[Vec<f64>]) -> Vec<Vec<usize>> {
+    let origin = map.size.map(|size| -i64::from(size / 2));
+    BiomeSampler::new(biomes, f64::from(map.sea_percent)).grid(noise_values, origin, 1)
+}
+
+/// Computes a tangent space normal map from the noise map using central differences
+fn generate_normal_map(map: &Map, noise_values: &[Vec<f64>], strength: f32) -> RgbaImage {
+    let [width, height] = map.size;
+    let height_at = |x: u32, y: u32| noise_values[x as usize][y as usize] as f32 / 100.0;
+    let image_buffer = RgbaImage::from_fn(width, height, |x, y| {
+        let dx = height_at((x + 1).min(width - 1), y) - height_at(x.saturating_sub(1), y);
+        let dy = height_at(x, (y + 1).min(height - 1)) - height_at(x, y.saturating_sub(1));
+        // Image rows go down while the green channel points up
+        let normal = Vec3::new(-dx * strength * 0.5, dy * strength * 0.5, 1.0).normalize();
+        let [r, g, b] = (normal * 0.5 + 0.5)
+            .to_array()
+            .map(|value| (value * 255.0).round() as u8);
+        image::Rgba([r, g, b, 255])
+    });
+    resize(map, image_buffer)
+}
 
-        ui_image.texture = images.add(map_texture);
+fn resize(map: &Map, image_buffer: RgbaImage) -> RgbaImage {
+    if map.same_size {
+        return image_buffer;
     }
+    DynamicImage::from(image_buffer)
+        .resize_exact(
+            map.image_size[0],
+            map.image_size[1],
+            if map.anti_aliasing {
+                FilterType::Triangle
+            } else {
+                FilterType::Nearest
+            },
+        )
+        .to_rgba8()
 }
@@ -0,0 +1,183 @@
+use image::{imageops::FilterType, GrayImage};
+use serde::{Deserialize, Serialize};
+
+/// Shape of the distance from the center of the map used by [`Falloff`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FalloffShape {
+    /// Euclidean distance, resulting in round islands
+    #[default]
+    Radial,
+    /// Largest distance along either axis, resulting in square continents
+    Square,
+}
+
+/// How the falloff mask is combined with the noise values
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FalloffMode {
+    /// Noise values are multiplied by the mask
+    #[default]
+    Multiply,
+    /// The inverse of the mask is subtracted from the noise values
+    Subtract,
+}
+
+/// Falloff mask lowering the noise values towards the edges of the map,
+/// so that maps are surrounded by ocean.
+/// The mask is 1 where the noise is kept and 0 where it is lowered the most
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Falloff {
+    /// Shape of the distance from the center
+    pub shape: FalloffShape,
+    /// Distance from the center, between 0 and 1, where the falloff starts.
+    /// The mask smoothly decreases from there to the edges
+    pub start: f64,
+    /// Custom curve as `[distance, mask]` points, linearly interpolated in order of distance.
+    /// If not empty, it is used instead of `start`
+    pub curve: Vec<[f64; 2]>,
+    /// Grayscale mask image stretched over the map, white keeping the noise.
+    /// If set, it is used instead of `shape`, `start` and `curve`.
+    /// Serialized as a base64 encoded PNG
+    #[serde(with = "png_base64")]
+    pub image: Option<GrayImage>,
+    /// How the mask is combined with the noise values
+    pub mode: FalloffMode,
+    /// Strength of the falloff, between 0 and 1
+    pub strength: f64,
+}
+
+/// (De)serializes an optional mask image as a base64 encoded PNG
+mod png_base64 {
+    use std::io::Cursor;
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use image::{DynamicImage, GrayImage, ImageFormat};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    // Serde passes the field by reference
+    #[allow(clippy::ref_option)]
+    pub fn serialize<S: Serializer>(
+        image: &Option<GrayImage>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let Some(image) = image else {
+            return serializer.serialize_none();
+        };
+        let mut png = Cursor::new(vec![]);
+        DynamicImage::ImageLuma8(image.clone())
+            .write_to(&mut png, ImageFormat::Png)
+            .map_err(|error| {
+                <S::Error as serde::ser::Error>::custom(format!("PNG encoding failed: {error}"))
+            })?;
+        serializer.serialize_some(&STANDARD.encode(png.into_inner()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<GrayImage>, D::Error> {
+        let Some(encoded) = Option::<String>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        let png = STANDARD
+            .decode(encoded)
+            .map_err(|error| D::Error::custom(format!("Invalid base64 falloff image: {error}")))?;
+        image::load_from_memory(&png)
+            .map(|image| Some(image.into_luma8()))
+            .map_err(|error| D::Error::custom(format!("Invalid falloff image: {error}")))
+    }
+}
+
+impl Default for Falloff {
+    fn default() -> Self {
+        Self {
+            shape: FalloffShape::default(),
+            start: 0.5,
+            curve: vec![],
+            image: None,
+            mode: FalloffMode::default(),
+            strength: 1.0,
+        }
+    }
+}
+
+impl Falloff {
+    /// Mask value at `distance` from the center, with `curve` sorted by distance
+    fn curve_at(&self, curve: &[[f64; 2]], distance: f64) -> f64 {
+        if curve.is_empty() {
+            let t =
+                ((distance - self.start) / (1.0 - self.start).max(f64::EPSILON)).clamp(0.0, 1.0);
+            // Smoothstep from 1 to 0
+            return (t * t).mul_add(2.0_f64.mul_add(t, -3.0), 1.0);
+        }
+        let [first, last] = [curve[0], curve[curve.len() - 1]];
+        if distance <= first[0] {
+            return first[1];
+        }
+        curve
+            .windows(2)
+            .find(|points| distance <= points[1][0])
+            .map_or(last[1], |points| {
+                let [[x0, y0], [x1, y1]] = [points[0], points[1]];
+                let t = (distance - x0) / (x1 - x0).max(f64::EPSILON);
+                t.mul_add(y1 - y0, y0)
+            })
+    }
+
+    /// Mask over a grid of `size` noise values
+    fn mask(&self, size: [usize; 2]) -> Vec<Vec<f64>> {
+        let [width, height] = size;
+        if let Some(image) = &self.image {
+            let image =
+                image::imageops::resize(image, width as u32, height as u32, FilterType::Triangle);
+            return (0..width)
+                .map(|x| {
+                    (0..height)
+                        .map(|y| f64::from(image.get_pixel(x as u32, y as u32)[0]) / 255.0)
+                        .collect()
+                })
+                .collect();
+        }
+        let mut curve = self.curve.clone();
+        curve.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        // Coordinates from -1 to 1 between the centers of the border cells
+        let coordinate = |index: usize, size: usize| {
+            (index as f64 * 2.0 / (size.saturating_sub(1).max(1)) as f64) - 1.0
+        };
+        (0..width)
+            .map(|x| {
+                (0..height)
+                    .map(|y| {
+                        let (u, v) = (coordinate(x, width), coordinate(y, height));
+                        let distance = match self.shape {
+                            FalloffShape::Radial => u.hypot(v),
+                            FalloffShape::Square => u.abs().max(v.abs()),
+                        };
+                        self.curve_at(&curve, distance)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Applies the falloff to a grid of noise values in the range [0, 100]
+    pub(crate) fn apply(&self, noise_values: &mut [Vec<f64>]) {
+        let size = [noise_values.len(), noise_values.first().map_or(0, Vec::len)];
+        if size.contains(&0) {
+            return;
+        }
+        let strength = self.strength.clamp(0.0, 1.0);
+        for (values, mask) in noise_values.iter_mut().zip(self.mask(size)) {
+            for (value, mask) in values.iter_mut().zip(mask) {
+                let mask = mask.clamp(0.0, 1.0);
+                *value = match self.mode {
+                    FalloffMode::Multiply => *value * strength.mul_add(mask - 1.0, 1.0),
+                    FalloffMode::Subtract => {
+                        ((1.0 - mask) * strength).mul_add(-100.0, *value).max(0.0)
+                    }
+                };
+            }
+        }
+    }
+}
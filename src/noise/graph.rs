@@ -0,0 +1,486 @@
+use noise::{BasicMulti, Billow, Fbm, HybridMulti, RidgedMulti};
+use noise::{MultiFractal, NoiseFn, Seedable};
+use noise::{OpenSimplex, Perlin, PerlinSurflet, Simplex, SuperSimplex, Value, Worley};
+use serde::{Deserialize, Serialize};
+
+use super::{Function, FunctionName, Method, Warp};
+
+/// Node of a noise graph.
+///
+/// Nodes are nested to compose sources, fractals, combiners and modifiers.
+/// A graph can be set on [`Noise`](super::Noise) or [`Planet`](crate::planet::Planet)
+/// in place of `method` and `function`.
+///
+/// # Example
+/// Ridged mountains masked by low frequency continents:
+/// ```json
+/// {
+///   "type": "select",
+///   "a": { "type": "constant", "value": -0.5 },
+///   "b": { "type": "fractal", "method": "perlin", "function": { "name": "ridgedMulti" } },
+///   "control": { "type": "source", "method": "simplex", "seed": 1 },
+///   "lower": 0.0,
+///   "upper": 1.0,
+///   "falloff": 0.2
+/// }
+/// ```
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum NoiseNode {
+    /// Base noise generator
+    Source {
+        /// Method used to generate noise
+        method: Method,
+        /// Added to the seed of the noise, so that layers using the same method differ
+        #[serde(default)]
+        seed: u32,
+    },
+    /// Base noise generator wrapped in a fractal function
+    Fractal {
+        /// Method used to generate noise
+        method: Method,
+        /// Fractal function applied on the method
+        #[serde(default)]
+        function: Function,
+        /// Added to the seed of the noise, so that layers using the same method differ
+        #[serde(default)]
+        seed: u32,
+    },
+    /// Outputs the same value everywhere
+    Constant {
+        /// Output value
+        value: f64,
+    },
+    /// Sum of both sources
+    Add {
+        /// First source
+        a: Box<Self>,
+        /// Second source
+        b: Box<Self>,
+    },
+    /// Product of both sources
+    Multiply {
+        /// First source
+        a: Box<Self>,
+        /// Second source
+        b: Box<Self>,
+    },
+    /// Smaller value of both sources
+    Min {
+        /// First source
+        a: Box<Self>,
+        /// Second source
+        b: Box<Self>,
+    },
+    /// Larger value of both sources
+    Max {
+        /// First source
+        a: Box<Self>,
+        /// Second source
+        b: Box<Self>,
+    },
+    /// Linear interpolation between `a` and `b`.
+    /// A control value of -1 outputs `a`, a control value of 1 outputs `b`
+    Blend {
+        /// First source
+        a: Box<Self>,
+        /// Second source
+        b: Box<Self>,
+        /// Source controlling the blend
+        control: Box<Self>,
+    },
+    /// Outputs `b` where the control value is within `lower` and `upper`, else outputs `a`
+    Select {
+        /// Source used outside of the bounds
+        a: Box<Self>,
+        /// Source used within the bounds
+        b: Box<Self>,
+        /// Source selecting between `a` and `b`
+        control: Box<Self>,
+        /// Lower bound of the selection
+        lower: f64,
+        /// Upper bound of the selection
+        upper: f64,
+        /// Smoothness of the transition at the bounds
+        #[serde(default)]
+        falloff: f64,
+    },
+    /// Absolute value of the source
+    Abs {
+        /// Source
+        source: Box<Self>,
+    },
+    /// Source clamped within `lower` and `upper`
+    Clamp {
+        /// Source
+        source: Box<Self>,
+        /// Lower bound
+        lower: f64,
+        /// Upper bound
+        upper: f64,
+    },
+    /// Remaps the source with a cubic curve through the control points.
+    /// Each control point is an `[input, output]` pair
+    Curve {
+        /// Source
+        source: Box<Self>,
+        /// Control points of the curve
+        points: Vec<[f64; 2]>,
+    },
+    /// Remaps the source with a terrace-forming curve through the control points
+    Terrace {
+        /// Source
+        source: Box<Self>,
+        /// Control points of the terraces
+        points: Vec<f64>,
+        /// If true, the terraces are inverted
+        #[serde(default)]
+        invert: bool,
+    },
+    /// Multiplies the source by `scale` and adds `bias`
+    ScaleBias {
+        /// Source
+        source: Box<Self>,
+        /// Multiplier
+        scale: f64,
+        /// Offset added after scaling
+        bias: f64,
+    },
+}
+
+impl NoiseNode {
+    pub(crate) fn build(&self, seed: u32) -> Graph {
+        let build = |node: &Self| Box::new(node.build(seed));
+        match self {
+            Self::Source {
+                method,
+                seed: node_seed,
+            } => Graph::Source(source(method, None, seed.wrapping_add(*node_seed))),
+            Self::Fractal {
+                method,
+                function,
+                seed: node_seed,
+            } => Graph::Source(source(
+                method,
+                Some(function),
+                seed.wrapping_add(*node_seed),
+            )),
+            Self::Constant { value } => Graph::Constant(*value),
+            Self::Add { a, b } => Graph::Add(build(a), build(b)),
+            Self::Multiply { a, b } => Graph::Multiply(build(a), build(b)),
+            Self::Min { a, b } => Graph::Min(build(a), build(b)),
+            Self::Max { a, b } => Graph::Max(build(a), build(b)),
+            Self::Blend { a, b, control } => Graph::Blend(build(a), build(b), build(control)),
+            Self::Select {
+                a,
+                b,
+                control,
+                lower,
+                upper,
+                falloff,
+            } => Graph::Select {
+                a: build(a),
+                b: build(b),
+                control: build(control),
+                bounds: [*lower, *upper],
+                falloff: falloff.clamp(0.0, (upper - lower).abs() / 2.0),
+            },
+            Self::Abs { source } => Graph::Abs(build(source)),
+            Self::Clamp {
+                source,
+                lower,
+                upper,
+            } => Graph::Clamp(build(source), [*lower, *upper]),
+            Self::Curve { source, points } => {
+                let mut points = points.clone();
+                points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+                Graph::Curve(build(source), points)
+            }
+            Self::Terrace {
+                source,
+                points,
+                invert,
+            } => {
+                let mut points = points.clone();
+                points.sort_by(f64::total_cmp);
+                Graph::Terrace(build(source), points, *invert)
+            }
+            Self::ScaleBias {
+                source,
+                scale,
+                bias,
+            } => Graph::ScaleBias(build(source), *scale, *bias),
+        }
+    }
+}
+
+pub fn build_graph(
+    method: &Method,
+    function: &Function,
+    graph: Option<&NoiseNode>,
+    warp: Option<&Warp>,
+    seed: u32,
+) -> Graph {
+    let graph = graph.map_or_else(
+        || Graph::Source(source(method, Some(function), seed)),
+        |graph| graph.build(seed),
+    );
+    match warp {
+        Some(warp) => Graph::Warp {
+            source: Box::new(graph),
+            warp: Box::new(warp.noise.build(seed)),
+            strength: warp.strength,
+            iterations: warp.iterations,
+        },
+        None => graph,
+    }
+}
+
+pub trait Source: NoiseFn<f64, 2> + NoiseFn<f64, 3> + NoiseFn<f64, 4> {}
+
+impl<T> Source for T where T: NoiseFn<f64, 2> + NoiseFn<f64, 3> + NoiseFn<f64, 4> {}
+
+/// [`SuperSimplex`] with 4D noise composed from two 3D samples,
+/// so that it can be sampled on a torus for tileable noise
+#[derive(Clone, Default)]
+pub struct SuperSimplex4D(SuperSimplex);
+
+impl Seedable for SuperSimplex4D {
+    fn set_seed(self, seed: u32) -> Self {
+        Self(self.0.set_seed(seed))
+    }
+
+    fn seed(&self) -> u32 {
+        self.0.seed()
+    }
+}
+
+impl NoiseFn<f64, 2> for SuperSimplex4D {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        self.0.get(point)
+    }
+}
+
+impl NoiseFn<f64, 3> for SuperSimplex4D {
+    fn get(&self, point: [f64; 3]) -> f64 {
+        self.0.get(point)
+    }
+}
+
+impl NoiseFn<f64, 4> for SuperSimplex4D {
+    fn get(&self, [x, y, z, w]: [f64; 4]) -> f64 {
+        (self.0.get([x, y, z]) + self.0.get([w, x + WARP_AXIS_OFFSET, y - WARP_AXIS_OFFSET]))
+            * std::f64::consts::FRAC_1_SQRT_2
+    }
+}
+
+/// Noise graph ready to be sampled
+pub enum Graph {
+    Source(Box<dyn Source>),
+    Constant(f64),
+    Add(Box<Self>, Box<Self>),
+    Multiply(Box<Self>, Box<Self>),
+    Min(Box<Self>, Box<Self>),
+    Max(Box<Self>, Box<Self>),
+    Blend(Box<Self>, Box<Self>, Box<Self>),
+    Select {
+        a: Box<Self>,
+        b: Box<Self>,
+        control: Box<Self>,
+        bounds: [f64; 2],
+        falloff: f64,
+    },
+    Abs(Box<Self>),
+    Clamp(Box<Self>, [f64; 2]),
+    Curve(Box<Self>, Vec<[f64; 2]>),
+    Terrace(Box<Self>, Vec<f64>, bool),
+    ScaleBias(Box<Self>, f64, f64),
+    Warp {
+        source: Box<Self>,
+        warp: Box<Self>,
+        strength: f64,
+        iterations: usize,
+    },
+}
+
+impl<const DIM: usize> NoiseFn<f64, DIM> for Graph
+where
+    dyn Source: NoiseFn<f64, DIM>,
+{
+    fn get(&self, point: [f64; DIM]) -> f64 {
+        match self {
+            Self::Source(source) => source.get(point),
+            Self::Constant(value) => *value,
+            Self::Add(a, b) => a.get(point) + b.get(point),
+            Self::Multiply(a, b) => a.get(point) * b.get(point),
+            Self::Min(a, b) => a.get(point).min(b.get(point)),
+            Self::Max(a, b) => a.get(point).max(b.get(point)),
+            Self::Blend(a, b, control) => {
+                let alpha = f64::midpoint(control.get(point), 1.0).clamp(0.0, 1.0);
+                lerp(a.get(point), b.get(point), alpha)
+            }
+            Self::Select {
+                a,
+                b,
+                control,
+                bounds: [lower, upper],
+                falloff,
+            } => {
+                let control = control.get(point);
+                if *falloff > 0.0 {
+                    if control < lower - falloff {
+                        a.get(point)
+                    } else if control < lower + falloff {
+                        let alpha = s_curve((control - (lower - falloff)) / (2.0 * falloff));
+                        lerp(a.get(point), b.get(point), alpha)
+                    } else if control < upper - falloff {
+                        b.get(point)
+                    } else if control < upper + falloff {
+                        let alpha = s_curve((control - (upper - falloff)) / (2.0 * falloff));
+                        lerp(b.get(point), a.get(point), alpha)
+                    } else {
+                        a.get(point)
+                    }
+                } else if control < *lower || control > *upper {
+                    a.get(point)
+                } else {
+                    b.get(point)
+                }
+            }
+            Self::Abs(source) => source.get(point).abs(),
+            Self::Clamp(source, [lower, upper]) => source.get(point).clamp(*lower, *upper),
+            Self::Curve(source, points) => curve(source.get(point), points),
+            Self::Terrace(source, points, invert) => terrace(source.get(point), points, *invert),
+            Self::ScaleBias(source, scale, bias) => source.get(point).mul_add(*scale, *bias),
+            Self::Warp {
+                source,
+                warp,
+                strength,
+                iterations,
+            } => {
+                let mut point = point;
+                for _ in 0..*iterations {
+                    let displaced = point;
+                    for (axis, value) in point.iter_mut().enumerate() {
+                        // Offset the warp noise per axis so that the axes are displaced independently
+                        let sample = displaced.map(|v| (axis as f64).mul_add(WARP_AXIS_OFFSET, v));
+                        *value = strength.mul_add(warp.get(sample), *value);
+                    }
+                }
+                source.get(point)
+            }
+        }
+    }
+}
+
+const WARP_AXIS_OFFSET: f64 = 17.31;
+
+fn lerp(a: f64, b: f64, alpha: f64) -> f64 {
+    (b - a).mul_add(alpha, a)
+}
+
+fn s_curve(alpha: f64) -> f64 {
+    alpha * alpha * 2.0f64.mul_add(-alpha, 3.0)
+}
+
+fn curve(value: f64, points: &[[f64; 2]]) -> f64 {
+    if points.is_empty() {
+        return value;
+    }
+    let last = points.len() - 1;
+    let position = points
+        .iter()
+        .position(|point| point[0] >= value)
+        .unwrap_or(points.len());
+    let index = |offset: isize| (position as isize + offset).clamp(0, last as isize) as usize;
+    let [i0, i1, i2, i3] = [index(-2), index(-1), index(0), index(1)];
+    if i1 == i2 {
+        return points[i1][1];
+    }
+    let alpha = (value - points[i1][0]) / (points[i2][0] - points[i1][0]);
+    let [n0, n1, n2, n3] = [points[i0][1], points[i1][1], points[i2][1], points[i3][1]];
+    let p = (n3 - n2) - (n0 - n1);
+    let q = (n0 - n1) - p;
+    let r = n2 - n0;
+    p.mul_add(alpha, q).mul_add(alpha, r).mul_add(alpha, n1)
+}
+
+fn terrace(value: f64, points: &[f64], invert: bool) -> f64 {
+    if points.is_empty() {
+        return value;
+    }
+    let last = points.len() - 1;
+    let position = points
+        .iter()
+        .position(|&point| point >= value)
+        .unwrap_or(points.len());
+    let i0 = position.saturating_sub(1).min(last);
+    let i1 = position.min(last);
+    if i0 == i1 {
+        return points[i1];
+    }
+    let (mut input0, mut input1) = (points[i0], points[i1]);
+    let mut alpha = (value - input0) / (input1 - input0);
+    if invert {
+        alpha = 1.0 - alpha;
+        std::mem::swap(&mut input0, &mut input1);
+    }
+    lerp(input0, input1, alpha * alpha)
+}
+
+fn source(method: &Method, function: Option<&Function>, seed: u32) -> Box<dyn Source> {
+    macro_rules! fractal {
+        ($fractal:ident, $function:expr) => {
+            match method {
+                Method::OpenSimplex => fractal::<$fractal<OpenSimplex>>(seed, $function),
+                Method::Perlin => fractal::<$fractal<Perlin>>(seed, $function),
+                Method::PerlinSurflet => fractal::<$fractal<PerlinSurflet>>(seed, $function),
+                Method::Simplex => fractal::<$fractal<Simplex>>(seed, $function),
+                Method::SuperSimplex => fractal::<$fractal<SuperSimplex4D>>(seed, $function),
+                Method::Value => fractal::<$fractal<Value>>(seed, $function),
+                Method::Worley => fractal::<$fractal<Worley>>(seed, $function),
+            }
+        };
+    }
+    match function.and_then(|function| function.name.as_ref().map(|name| (name, function))) {
+        None => match method {
+            Method::OpenSimplex => seeded::<OpenSimplex>(seed),
+            Method::Perlin => seeded::<Perlin>(seed),
+            Method::PerlinSurflet => seeded::<PerlinSurflet>(seed),
+            Method::Simplex => seeded::<Simplex>(seed),
+            Method::SuperSimplex => seeded::<SuperSimplex4D>(seed),
+            Method::Value => seeded::<Value>(seed),
+            Method::Worley => seeded::<Worley>(seed),
+        },
+        Some((FunctionName::BasicMulti, function)) => fractal!(BasicMulti, function),
+        Some((FunctionName::Billow, function)) => fractal!(Billow, function),
+        Some((FunctionName::Fbm, function)) => fractal!(Fbm, function),
+        Some((FunctionName::HybridMulti, function)) => fractal!(HybridMulti, function),
+        Some((FunctionName::RidgedMulti, function)) => fractal!(RidgedMulti, function),
+    }
+}
+
+fn seeded<T>(seed: u32) -> Box<dyn Source>
+where
+    T: Default + Seedable + Source + 'static,
+{
+    Box::new(T::default().set_seed(seed))
+}
+
+fn fractal<T>(seed: u32, function: &Function) -> Box<dyn Source>
+where
+    T: Default + Seedable + MultiFractal + Source + 'static,
+{
+    Box::new(
+        T::default()
+            .set_seed(seed)
+            .set_octaves(function.octaves)
+            .set_frequency(function.frequency)
+            .set_lacunarity(function.lacunarity)
+            .set_persistence(function.persistence),
+    )
+}
@@ -1,13 +1,20 @@
-use core::fmt;
+use core::{f64::consts::TAU, fmt};
 
 use bevy::prelude::{Handle, Image};
+use image::{Pixel, RgbaImage};
 use noise::{BasicMulti, Billow, Fbm, HybridMulti, RidgedMulti};
 use noise::{MultiFractal, NoiseFn, Seedable};
 use noise::{OpenSimplex, Perlin, PerlinSurflet, Simplex, SuperSimplex, Value, Worley};
 use serde::{Deserialize, Serialize};
 
+mod falloff;
+mod graph;
+pub use falloff::{Falloff, FalloffMode, FalloffShape};
+pub use graph::NoiseNode;
+pub(crate) use graph::{build_graph, Graph};
+
 /// 2D noise method used to generate noise map
-#[derive(PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Method {
     /// Open Simplex noise
@@ -41,7 +48,7 @@ impl fmt::Display for Method {
 }
 
 /// Fractal function that should be applied on the noise values
-#[derive(PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum FunctionName {
     /// See [`BasicMulti`](https://docs.rs/noise/latest/noise/struct.BasicMulti.html)
@@ -69,7 +76,7 @@ impl fmt::Display for FunctionName {
 }
 
 /// Fractal function configuration
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(default, rename_all = "camelCase")]
 pub struct Function {
     /// Name of the function
@@ -96,8 +103,35 @@ impl Default for Function {
     }
 }
 
+/// Domain warping configuration.
+/// The sample coordinates are displaced by a second noise before the noise is sampled
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Warp {
+    /// Noise displacing the sample coordinates
+    pub noise: NoiseNode,
+    /// Distance by which the sample coordinates are displaced
+    pub strength: f64,
+    /// Number of times the displacement is applied
+    pub iterations: usize,
+}
+
+impl Default for Warp {
+    fn default() -> Self {
+        Self {
+            noise: NoiseNode::Fractal {
+                method: Method::Perlin,
+                function: Function::default(),
+                seed: 1,
+            },
+            strength: 1.0,
+            iterations: 1,
+        }
+    }
+}
+
 /// Region based on height
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(default, rename_all = "camelCase")]
 pub struct Region {
     /// Label of the region
@@ -119,7 +153,7 @@ impl Default for Region {
 }
 
 /// Gradient used to map color values
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(default, rename_all = "camelCase")]
 pub struct Gradient {
     /// Image handle of gradient
@@ -145,7 +179,7 @@ impl Default for Gradient {
 }
 
 /// Noise configuration
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(default, rename_all = "camelCase")]
 pub struct Noise {
     pub(crate) size: [u32; 2],
@@ -159,6 +193,18 @@ pub struct Noise {
     pub method: Method,
     /// Function used to generate noise
     pub function: Function,
+    /// Noise graph used to generate noise.
+    /// If set, `method` and `function` are ignored
+    pub graph: Option<NoiseNode>,
+    /// Domain warping applied before sampling the noise
+    pub warp: Option<Warp>,
+    /// If true, the noise map repeats seamlessly in both axes.
+    /// Noise is sampled on a 4D torus instead of a plane, and erosion and rivers
+    /// wrap around the edges of the map
+    pub tileable: bool,
+    /// Falloff mask lowering the noise towards the edges, to generate islands and continents.
+    /// The mask depends on the size of the map, so it is not applied to terrain chunks
+    pub falloff: Option<Falloff>,
     /// Vector of regions
     pub regions: Vec<Region>,
     /// Gradient determines how the noise values are mapped to colors
@@ -177,6 +223,10 @@ impl Default for Noise {
             offset: [0.0; 2],
             method: Method::Perlin,
             function: Function::default(),
+            graph: None,
+            warp: None,
+            tileable: false,
+            falloff: None,
             regions: vec![
                 Region {
                     label: "Region #1".to_string(),
@@ -195,7 +245,83 @@ impl Default for Noise {
     }
 }
 
+/// Builds the color gradient from the regions along with its preview image
+pub(crate) fn generate_gradient(
+    regions: &[Region],
+    gradient: &Gradient,
+    base_color: [u8; 4],
+) -> Result<(colorgrad::Gradient, RgbaImage), String> {
+    let mut colors: Vec<colorgrad::Color> = Vec::with_capacity(regions.len());
+    let mut domain: Vec<f64> = Vec::with_capacity(regions.len());
+    for region in regions {
+        colors.push(colorgrad::Color {
+            r: f64::from(region.color[0]) / 255.0,
+            g: f64::from(region.color[1]) / 255.0,
+            b: f64::from(region.color[2]) / 255.0,
+            a: f64::from(region.color[3]) / 255.0,
+        });
+        domain.push(region.position);
+    }
+    let mut grad = colorgrad::CustomGradient::new()
+        .colors(&colors)
+        .domain(&domain)
+        .build()
+        .or_else(|_| colorgrad::CustomGradient::new().colors(&colors).build())
+        .map_err(|error| format!("Gradient generation failed: {error}"))?;
+
+    if gradient.segments != 0 {
+        grad = grad.sharp(gradient.segments, gradient.smoothness);
+    }
+
+    let mut gradient_buffer =
+        RgbaImage::from_pixel(gradient.size[0], gradient.size[1], image::Rgba(base_color));
+
+    for (x, _, pixel) in gradient_buffer.enumerate_pixels_mut() {
+        let rgba = grad
+            .at(f64::from(x) * 100.0 / f64::from(gradient.size[0]))
+            .to_rgba8();
+        pixel.blend(&image::Rgba(rgba));
+    }
+    Ok((grad, gradient_buffer))
+}
+
+/// Index of the region whose band of the gradient contains `height` in percent, that is the
+/// last region starting at or below it. Lower heights belong to the first region,
+/// as the gradient is clamped to its first color
+pub(crate) fn region_at(regions: &[Region], height: f64) -> Option<usize> {
+    if regions.is_empty() {
+        return None;
+    }
+    Some(
+        regions
+            .iter()
+            .rposition(|region| region.position <= height)
+            .unwrap_or(0),
+    )
+}
+
 pub(crate) fn generate_noise_map(noise: &Noise) -> Vec<Vec<f64>> {
+    let mut noise_map = generate_raw_noise_map(noise);
+    if let Some(falloff) = &noise.falloff {
+        falloff.apply(&mut noise_map);
+    }
+    noise_map
+}
+
+fn generate_raw_noise_map(noise: &Noise) -> Vec<Vec<f64>> {
+    if noise.graph.is_some() || noise.warp.is_some() || noise.tileable {
+        let graph = build_graph(
+            &noise.method,
+            &noise.function,
+            noise.graph.as_ref(),
+            noise.warp.as_ref(),
+            noise.seed,
+        );
+        if noise.tileable {
+            return generate_tileable_noise_vector(graph, noise.size, noise.scale, noise.offset);
+        }
+        return generate_noise_vector(graph, noise.size, noise.scale, noise.offset);
+    }
     noise.function.name.as_ref().map_or_else(
         || {
             let generate_noise_map = match noise.method {
@@ -268,6 +394,24 @@ pub(crate) fn generate_noise_map(noise: &Noise) -> Vec<Vec<f64>> {
     )
 }
 
+/// Samples `noise` on a grid of `size` samples starting at the global sample index `origin`,
+/// taking every `step`th sample, see [`generate_noise_grid`]. `tileable` is ignored
+pub(crate) fn generate_noise_map_at(
+    noise: &Noise,
+    origin: [i64; 2],
+    size: [u32; 2],
+    step: u32,
+) -> Vec<Vec<f64>> {
+    let graph = build_graph(
+        &noise.method,
+        &noise.function,
+        noise.graph.as_ref(),
+        noise.warp.as_ref(),
+        noise.seed,
+    );
+    generate_noise_grid(graph, origin, size, step, noise.scale, noise.offset)
+}
+
 pub(crate) fn generate_noise<T>(
     size: [u32; 2],
     seed: u32,
@@ -306,15 +450,61 @@ pub(crate) fn generate_noise_vector(
     size: [u32; 2],
     scale: f64,
     offset: [f64; 2],
+) -> Vec<Vec<f64>> {
+    let origin = size.map(|size| -i64::from(size / 2));
+    generate_noise_grid(noise, origin, size, 1, scale, offset)
+}
+
+/// Samples the noise on a grid of `size` samples starting at the global sample index `origin`,
+/// taking every `step`th sample.
+/// Samples only depend on their global index, so grids sharing samples match exactly
+pub(crate) fn generate_noise_grid(
+    noise: impl NoiseFn<f64, 2>,
+    origin: [i64; 2],
+    size: [u32; 2],
+    step: u32,
+    scale: f64,
+    offset: [f64; 2],
 ) -> Vec<Vec<f64>> {
     let mut noise_vector: Vec<Vec<f64>> = Vec::with_capacity(size[0] as usize);
     let noise = noise::Clamp::new(noise).set_bounds(-1.0, 1.0);
     for i in 0..size[0] {
         let mut row: Vec<f64> = Vec::with_capacity(size[1] as usize);
         for j in 0..size[1] {
-            let x = f64::from(i as i32 - (size[0] / 2) as i32) / scale + offset[0];
-            let y = f64::from(j as i32 - (size[1] / 2) as i32) / scale + offset[1];
-            let value = (noise.get([x, y]) + 1.0) / 2.0 * 100.0;
+            let x = (origin[0] + i64::from(i * step)) as f64 / scale + offset[0];
+            let y = (origin[1] + i64::from(j * step)) as f64 / scale + offset[1];
+            let value = f64::midpoint(noise.get([x, y]), 1.0) * 100.0;
+            row.push(value);
+        }
+        noise_vector.push(row);
+    }
+    noise_vector
+}
+
+pub(crate) fn generate_tileable_noise_vector(
+    noise: impl NoiseFn<f64, 4>,
+    size: [u32; 2],
+    scale: f64,
+    offset: [f64; 2],
+) -> Vec<Vec<f64>> {
+    let mut noise_vector: Vec<Vec<f64>> = Vec::with_capacity(size[0] as usize);
+    let noise = noise::Clamp::new(noise).set_bounds(-1.0, 1.0);
+    let [width, height] = size.map(f64::from);
+    // Circumference of the circles matches the size of the map,
+    // so that tileable noise has the same frequency as non tileable noise
+    let radius = [width / scale / TAU, height / scale / TAU];
+    for i in 0..size[0] {
+        let mut row: Vec<f64> = Vec::with_capacity(size[1] as usize);
+        let u = TAU * offset[0].mul_add(scale, f64::from(i)) / width;
+        for j in 0..size[1] {
+            let v = TAU * offset[1].mul_add(scale, f64::from(j)) / height;
+            let point = [
+                radius[0] * u.cos(),
+                radius[0] * u.sin(),
+                radius[1] * v.cos(),
+                radius[1] * v.sin(),
+            ];
+            let value = f64::midpoint(noise.get(point), 1.0) * 100.0;
             row.push(value);
         }
         noise_vector.push(row);
@@ -435,3 +625,15 @@ where
     let noise = noise::Clamp::new(noise).set_bounds(-1.0, 1.0);
     noise.get([x, y, z])
 }
+
+pub(crate) fn graph_noise_at_point_3d(
+    point: [f64; 3],
+    scale: f64,
+    offset: [f64; 3],
+    graph: &Graph,
+) -> f64 {
+    let x = point[0] / scale + offset[0];
+    let y = point[1] / scale + offset[1];
+    let z = point[2] / scale + offset[2];
+    graph.get([x, y, z]).clamp(-1.0, 1.0)
+}
@@ -0,0 +1,736 @@
+//! Generate planet
+//! # Example
+//! For configuration, see [`Planet`](struct.Planet.html)
+//! ```no_run
+//! use bevy::prelude::*;
+//! use bevy_generative::planet::{PlanetBundle, PlanetPlugin};
+//!
+//! fn main() {
+//!     App::new()
+//!         .add_plugins(DefaultPlugins)
+//!         .add_plugins(PlanetPlugin)
+//!         .add_systems(Startup, setup)
+//!         .run();
+//! }
+//!
+//! fn setup(mut commands: Commands) {
+//!     commands.spawn(PointLightBundle {
+//!         transform: Transform::from_xyz(-2.0, 2.5, 5.0).looking_at(Vec3::ZERO, Vec3::Y),
+//!         ..default()
+//!     });
+//!     commands.spawn(Camera3dBundle {
+//!         transform: Transform::from_xyz(-2.0, 2.5, 5.0).looking_at(Vec3::ZERO, Vec3::Y),
+//!         ..default()
+//!     });
+//!     commands.spawn(PlanetBundle::default());
+//! }
+//! ```
+use bevy::{
+    prelude::{
+        App, Assets, Bundle, Changed, Commands, Component, DetectChangesMut, Entity, Handle, Has,
+        Image, IntoSystemConfigs, Mesh, Or, PbrBundle, Plugin, Query, Ray3d, ResMut,
+        StandardMaterial, UVec2, Update, Vec3,
+    },
+    render::{render_asset::RenderAssetUsages, render_resource::PrimitiveTopology},
+};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::{
+    biome::{BiomeSampler, Biomes},
+    export::ModelFormat,
+    noise::{
+        build_graph, generate_gradient, get_noise_at_point_3d, graph_noise_at_point_3d, region_at,
+        Function, Gradient, Graph, Method, NoiseNode, Region, Warp,
+    },
+    util::{
+        export_model, image_from_buffer, mesh_to_glb, replace_asset, write_model, GenerationTask,
+    },
+    GenerationStatus, RayHit,
+};
+
+mod quadtree;
+
+pub use quadtree::{PlanetNode, PlanetQuadtree};
+
+/// Directions of the cube faces the planet is built from
+const FACES: [Vec3; 6] = [
+    Vec3::Y,
+    Vec3::NEG_Y,
+    Vec3::X,
+    Vec3::NEG_X,
+    Vec3::Z,
+    Vec3::NEG_Z,
+];
+
+/// Component for planet configuration
+#[derive(Component, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Planet {
+    /// Seed of the noise
+    pub seed: u32,
+    /// Scale of the noise
+    pub scale: f64,
+    /// Offset of the noise
+    pub offset: [f64; 3],
+    /// Method used to generate noise
+    pub method: Method,
+    /// Function used to generate noise
+    pub function: Function,
+    /// Noise graph used to generate noise.
+    /// If set, `method` and `function` are ignored
+    pub graph: Option<NoiseNode>,
+    /// Domain warping applied before sampling the noise
+    pub warp: Option<Warp>,
+    /// Resolution of planet mesh
+    pub resolution: u32,
+    /// Gradient determines how the noise values are mapped to colors
+    pub gradient: Gradient,
+    /// Base color of the gradient.
+    /// If gradient has transparency, base color will be blended with the gradient
+    pub base_color: [u8; 4],
+    /// Vector of regions
+    pub regions: Vec<Region>,
+    /// If true, renders planet mesh as wireframe
+    pub wireframe: bool,
+    /// Height values are raised to this value.
+    /// Lower values result in plains, higher values result in mountains
+    pub height_exponent: f32,
+    /// Percentage of planet that should appear under sea
+    /// The mesh below this value will be flat
+    pub sea_percent: f32,
+    /// Depth of the skirts hanging from the borders of [`PlanetNode`] meshes,
+    /// hiding cracks between nodes at a different level of the quadtree
+    pub skirt_depth: f32,
+    /// If set, the planet is colored by biome instead of by the gradient.
+    /// Temperature decreases with altitude and towards the poles, see [`Biomes::latitude_cooling`]
+    pub biomes: Option<Biomes>,
+    /// Format used when exporting the model
+    pub export_format: ModelFormat,
+    /// If true, exports model in `export_format`
+    /// Native: Shows save file dialog.
+    /// WASM: Downloads model based on browser configuration.
+    #[serde(skip)]
+    pub export: bool,
+}
+
+impl Default for Planet {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            scale: 20.0,
+            offset: [0.0; 3],
+            method: Method::Perlin,
+            function: Function::default(),
+            graph: None,
+            warp: None,
+            resolution: 20,
+            regions: vec![
+                Region {
+                    label: "Region #1".to_string(),
+                    color: [255, 0, 0, 255],
+                    position: 0.0,
+                },
+                Region {
+                    label: "Region #2".to_string(),
+                    color: [0, 0, 255, 255],
+                    position: 100.0,
+                },
+            ],
+            gradient: Gradient::default(),
+            base_color: [255, 255, 255, 255],
+            wireframe: false,
+            height_exponent: 1.5,
+            sea_percent: 50.0,
+            skirt_depth: 0.02,
+            biomes: None,
+            export_format: ModelFormat::default(),
+            export: false,
+        }
+    }
+}
+
+impl Planet {
+    /// Generates the planet mesh and encodes it as binary glTF, without opening a file dialog
+    ///
+    /// # Errors
+    /// Returns an error if generation or encoding fails
+    pub fn to_glb_bytes(&self) -> Result<Vec<u8>, String> {
+        let (_, mesh) = generate_mesh(self)?;
+        mesh_to_glb(&mesh, None)
+    }
+
+    /// Generates the planet mesh and writes it in `export_format` to `path`, without opening a file dialog.
+    /// Files are named after the file stem of `path`, some formats write several files, see [`ModelFormat`]
+    ///
+    /// # Errors
+    /// Returns an error if generation, encoding or writing the files fails
+    pub fn export_to_path(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let (_, mesh) = generate_mesh(self)?;
+        write_model(&mesh, None, self.export_format, path.as_ref())
+    }
+
+    /// Surface of the planet in `direction` from its center, see [`PlanetSampler::surface_at`].
+    /// To sample many directions, build a [`PlanetSampler`] once instead
+    ///
+    /// # Errors
+    /// Returns an error if the gradient can not be generated
+    pub fn surface_at(&self, direction: Vec3) -> Result<PlanetSurface, String> {
+        Ok(self.sampler()?.surface_at(direction))
+    }
+
+    /// First intersection of `ray` with the surface, see [`PlanetSampler::raycast`].
+    /// To cast many rays, build a [`PlanetSampler`] once instead
+    ///
+    /// # Errors
+    /// Returns an error if the gradient can not be generated
+    pub fn raycast(&self, ray: Ray3d) -> Result<Option<RayHit>, String> {
+        Ok(self.sampler()?.raycast(ray))
+    }
+
+    /// Builds the noise, gradient and biomes of the planet once, to sample its surface
+    /// or cast rays against it many times
+    ///
+    /// # Errors
+    /// Returns an error if the gradient can not be generated
+    pub fn sampler(&self) -> Result<PlanetSampler<'_>, String> {
+        let (grad, _) = generate_gradient(&self.regions, &self.gradient, self.base_color)?;
+        Ok(PlanetSampler {
+            planet: self,
+            graph: build_planet_graph(self),
+            grad,
+            biomes: self.biomes.as_ref().map(|biomes| {
+                (
+                    biomes,
+                    BiomeSampler::new(biomes, f64::from(self.sea_percent)),
+                )
+            }),
+        })
+    }
+}
+
+/// Surface of a [`Planet`] with its noise, gradient and biomes built once, see [`Planet::sampler`]
+pub struct PlanetSampler<'a> {
+    planet: &'a Planet,
+    graph: Option<Graph>,
+    grad: colorgrad::Gradient,
+    biomes: Option<(&'a Biomes, BiomeSampler<'a>)>,
+}
+
+impl PlanetSampler<'_> {
+    /// Surface of the planet in `direction` from its center, computed like the vertices of the mesh.
+    /// Between vertices, the mesh interpolates linearly while the surface follows the noise
+    #[must_use]
+    pub fn surface_at(&self, direction: Vec3) -> PlanetSurface {
+        let planet = self.planet;
+        let direction = direction.try_normalize().unwrap_or(Vec3::Y);
+        let noise_value = surface_noise(planet, self.graph.as_ref(), direction);
+        let radius = surface_radius(planet, noise_value);
+        let normal_step = 0.5 / planet.resolution.max(1) as f32;
+        let (color, biome) =
+            surface_color(&self.grad, self.biomes.as_ref(), noise_value, direction);
+        let height = f64::from(noise_value) * 100.0;
+        PlanetSurface {
+            radius,
+            position: direction * radius,
+            normal: surface_normal(planet, self.graph.as_ref(), direction, normal_step),
+            noise_value,
+            region: region_at(&planet.regions, height),
+            biome,
+            color,
+        }
+    }
+
+    /// First intersection of `ray` with the surface, in the local space of the planet.
+    /// The ray is marched from the sphere enclosing the displaced surface to the unit sphere
+    /// under it in steps of about the vertex spacing, then the crossing is refined by bisection
+    #[must_use]
+    pub fn raycast(&self, ray: Ray3d) -> Option<RayHit> {
+        let (planet, graph) = (self.planet, &self.graph);
+        let outer = surface_radius(planet, 1.0);
+        // Intersection of the ray with the sphere enclosing the surface
+        let along = ray.origin.dot(*ray.direction);
+        let discriminant = along.mul_add(along, outer.mul_add(outer, -ray.origin.length_squared()));
+        if discriminant < 0.0 {
+            return None;
+        }
+        let (near, mut far) = (
+            (-along - discriminant.sqrt()).max(0.0),
+            -along + discriminant.sqrt(),
+        );
+        if far < 0.0 {
+            return None;
+        }
+        // The surface is never below the unit sphere, so the march stops just inside of it, where
+        // the ray is below the sea too
+        let step = 1.0 / planet.resolution.max(1) as f32;
+        let inner = 1.0 - step;
+        let discriminant = along.mul_add(along, inner.mul_add(inner, -ray.origin.length_squared()));
+        if discriminant >= 0.0 && -along - discriminant.sqrt() >= near {
+            far = far.min(-along - discriminant.sqrt());
+        }
+
+        // Distance of the ray above the surface
+        let above = |distance: f32| {
+            let point = ray.get_point(distance);
+            let direction = point.try_normalize().unwrap_or(Vec3::Y);
+            point.length()
+                - surface_radius(planet, surface_noise(planet, graph.as_ref(), direction))
+        };
+        let mut previous = (near, above(near));
+        for index in 1..=((far - near) / step).ceil() as u32 {
+            let distance = (index as f32).mul_add(step, near).min(far);
+            let height = above(distance);
+            if previous.1 >= 0.0 && height < 0.0 {
+                let (mut low, mut high) = (previous.0, distance);
+                for _ in 0..24 {
+                    let middle = (low + high) * 0.5;
+                    if above(middle) < 0.0 {
+                        high = middle;
+                    } else {
+                        low = middle;
+                    }
+                }
+                let point = ray.get_point(high);
+                let direction = point.try_normalize().unwrap_or(Vec3::Y);
+                return Some(RayHit {
+                    point,
+                    normal: surface_normal(planet, graph.as_ref(), direction, step * 0.5),
+                    distance: high,
+                });
+            }
+            previous = (distance, height);
+        }
+        None
+    }
+}
+
+/// Surface of a [`Planet`] in a direction, see [`PlanetSampler::surface_at`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlanetSurface {
+    /// Distance from the center of the planet, displaced by the noise
+    pub radius: f32,
+    /// Point on the surface, relative to the center of the planet
+    pub position: Vec3,
+    /// Normal of the surface, the up vector of objects standing on it
+    pub normal: Vec3,
+    /// Noise value in the range [0, 1]
+    pub noise_value: f32,
+    /// Index of the region of the gradient band the noise value in percent falls in,
+    /// the last region whose position is at or below it
+    pub region: Option<usize>,
+    /// Biome ID if `biomes` is set
+    pub biome: Option<usize>,
+    /// Color of the surface, as in the vertex colors of the mesh
+    pub color: [f32; 4],
+}
+
+/// Render `Planet` as a `PbrBundle`
+#[derive(Bundle, Default)]
+pub struct PlanetBundle {
+    /// Planet configuration
+    pub planet: Planet,
+    /// Generated mesh data is written to `PbrBundle`
+    pub pbr_bundle: PbrBundle,
+}
+
+/// Plugin to generate planet.
+/// The planet is only regenerated when its [`Planet`] component changes.
+/// Generation runs in the background, see [`GenerationStatus`]
+pub struct PlanetPlugin;
+
+impl Plugin for PlanetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                quadtree::update_planet_quadtrees,
+                spawn_planet_tasks,
+                apply_planet_tasks,
+            )
+                .chain(),
+        );
+    }
+}
+
+struct MeshData {
+    positions: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    colors: Vec<[f32; 4]>,
+}
+
+#[derive(Component)]
+struct PlanetTask(GenerationTask<PlanetOutput>);
+
+struct PlanetOutput {
+    gradient: RgbaImage,
+    mesh: Mesh,
+    export: bool,
+}
+
+fn spawn_planet_tasks(
+    mut commands: Commands,
+    mut query: Query<
+        (Entity, &mut Planet, Option<&PlanetNode>),
+        Or<(Changed<Planet>, Changed<PlanetNode>)>,
+    >,
+) {
+    for (entity, mut planet, node) in &mut query {
+        let node = node.copied();
+        let planet = planet.bypass_change_detection();
+        let config = planet.clone();
+        planet.export = false;
+        let task = GenerationTask::spawn(move || {
+            let (gradient, mesh) = match node {
+                Some(node) => generate_node_mesh(&config, node)?,
+                None => generate_mesh(&config)?,
+            };
+            Ok(PlanetOutput {
+                gradient,
+                mesh,
+                export: config.export,
+            })
+        });
+        commands
+            .entity(entity)
+            .insert((PlanetTask(task), GenerationStatus::Pending));
+    }
+}
+
+fn apply_planet_tasks(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut query: Query<(
+        Entity,
+        &mut Planet,
+        &mut Handle<Mesh>,
+        &Handle<StandardMaterial>,
+        &mut PlanetTask,
+        Has<PlanetNode>,
+    )>,
+) {
+    for (entity, mut planet, mut mesh_handle, material, mut task, is_node) in &mut query {
+        let Some(result) = task.0.poll() else {
+            continue;
+        };
+        let mut entity = commands.entity(entity);
+        entity.remove::<PlanetTask>();
+        match result {
+            Ok(output) => {
+                // Nodes share the material of their quadtree, which is left as configured
+                if !is_node {
+                    if let Some(material) = materials.get_mut(material) {
+                        *material = StandardMaterial::default();
+                    }
+                }
+                let planet = planet.bypass_change_detection();
+                replace_asset(
+                    &mut images,
+                    &mut planet.gradient.image,
+                    image_from_buffer(output.gradient),
+                );
+                if output.export {
+                    export_model(&output.mesh, None, planet.export_format, "planet");
+                }
+                replace_asset(&mut meshes, &mut mesh_handle, output.mesh);
+                entity.insert(GenerationStatus::Ready);
+            }
+            Err(error) => {
+                entity.insert(GenerationStatus::Failed(error));
+            }
+        }
+    }
+}
+
+pub(crate) fn generate_mesh(planet: &Planet) -> Result<(RgbaImage, Mesh), String> {
+    let (grad, gradient_buffer) =
+        generate_gradient(&planet.regions, &planet.gradient, planet.base_color)?;
+    let graph = build_planet_graph(planet);
+
+    let mut mesh_data = MeshData {
+        positions: vec![],
+        indices: vec![],
+        normals: vec![],
+        uvs: vec![],
+        colors: vec![],
+    };
+    for direction in FACES {
+        let face = generate_face(
+            planet,
+            direction,
+            &grad,
+            graph.as_ref(),
+            0,
+            UVec2::ZERO,
+            0.0,
+        );
+        let index_start = mesh_data.positions.len() as u32;
+        mesh_data.positions.extend(face.positions);
+        mesh_data
+            .indices
+            .extend(face.indices.iter().map(|index| index + index_start));
+        mesh_data.normals.extend(face.normals);
+        mesh_data.uvs.extend(face.uvs);
+        mesh_data.colors.extend(face.colors);
+    }
+    Ok((gradient_buffer, build_mesh(planet, mesh_data)))
+}
+
+/// Generates the mesh of the quadtree `node`, covering its patch of a cube face
+pub(crate) fn generate_node_mesh(
+    planet: &Planet,
+    node: PlanetNode,
+) -> Result<(RgbaImage, Mesh), String> {
+    let (grad, gradient_buffer) =
+        generate_gradient(&planet.regions, &planet.gradient, planet.base_color)?;
+    let graph = build_planet_graph(planet);
+    let mesh_data = generate_face(
+        planet,
+        FACES[usize::from(node.face % 6)],
+        &grad,
+        graph.as_ref(),
+        node.level,
+        node.coord,
+        planet.skirt_depth,
+    );
+    Ok((gradient_buffer, build_mesh(planet, mesh_data)))
+}
+
+fn build_planet_graph(planet: &Planet) -> Option<Graph> {
+    (planet.graph.is_some() || planet.warp.is_some()).then(|| {
+        build_graph(
+            &planet.method,
+            &planet.function,
+            planet.graph.as_ref(),
+            planet.warp.as_ref(),
+            planet.seed,
+        )
+    })
+}
+
+fn build_mesh(planet: &Planet, mesh_data: MeshData) -> Mesh {
+    let MeshData {
+        positions,
+        mut indices,
+        normals,
+        uvs,
+        colors,
+    } = mesh_data;
+    if planet.wireframe {
+        let triangle_number = indices.len() / 3;
+        let cloned_indices = indices.clone();
+        indices = vec![];
+        for i in 0..triangle_number {
+            for j in &[0, 1, 1, 2, 2, 0] {
+                indices.push(cloned_indices[i * 3 + j]);
+            }
+        }
+    }
+
+    let mut mesh = if planet.wireframe {
+        Mesh::new(PrimitiveTopology::LineList, RenderAssetUsages::RENDER_WORLD)
+    } else {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::RENDER_WORLD,
+        )
+    };
+    mesh.insert_indices(bevy::render::mesh::Indices::U32(indices));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh
+}
+
+/// Point on the cube face `local_up` at `percent` along its two axes, projected on the unit sphere
+fn face_point(local_up: Vec3, [x_percent, y_percent]: [f32; 2]) -> Vec3 {
+    let axis_a = Vec3::new(local_up.y, local_up.z, local_up.x);
+    let axis_b = local_up.cross(axis_a);
+    (local_up + (x_percent - 0.5) * 2.0 * axis_a + (y_percent - 0.5) * 2.0 * axis_b).normalize()
+}
+
+/// Generates the patch of the cube face `local_up` at `coord` in the grid of `2^level` patches
+/// per side. Vertices are placed on a grid shared by the whole face, so adjacent patches of the
+/// same level match exactly. If `skirt_depth` is positive, walls of that depth hang from the
+/// border, hiding cracks along patches of a different level
+fn generate_face(
+    planet: &Planet,
+    local_up: Vec3,
+    grad: &colorgrad::Gradient,
+    graph: Option<&Graph>,
+    level: u32,
+    coord: UVec2,
+    skirt_depth: f32,
+) -> MeshData {
+    let vertices_count = (planet.resolution * planet.resolution) as usize;
+    let triangle_count = ((planet.resolution - 1) * (planet.resolution - 1) * 6) as usize;
+    let mut positions: Vec<[f32; 3]> = Vec::with_capacity(vertices_count);
+    let mut indices: Vec<u32> = Vec::with_capacity(triangle_count);
+    let mut normals: Vec<[f32; 3]> = Vec::with_capacity(vertices_count);
+    let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(vertices_count);
+    let mut colors: Vec<[f32; 4]> = Vec::with_capacity(vertices_count);
+
+    let resolution = planet.resolution + 1;
+    let face_resolution = planet.resolution << level;
+    let normal_step = 0.5 / face_resolution as f32;
+    let mut radii: Vec<f32> = Vec::with_capacity(vertices_count);
+    let biomes = planet.biomes.as_ref().map(|biomes| {
+        (
+            biomes,
+            BiomeSampler::new(biomes, f64::from(planet.sea_percent)),
+        )
+    });
+    for y in 0..resolution {
+        for x in 0..resolution {
+            let x_percent = (coord.x * planet.resolution + x) as f32 / face_resolution as f32;
+            let y_percent = (coord.y * planet.resolution + y) as f32 / face_resolution as f32;
+            let vertex = face_point(local_up, [x_percent, y_percent]);
+            let noise_value = surface_noise(planet, graph, vertex);
+            let normal = surface_normal(planet, graph, vertex, normal_step);
+            let radius = surface_radius(planet, noise_value);
+            radii.push(radius);
+            let (color, _) = surface_color(grad, biomes.as_ref(), noise_value, vertex);
+            let vertex = vertex * radius;
+            let i = x + y * resolution;
+            positions.push([vertex.x, vertex.y, vertex.z]);
+            normals.push(normal.to_array());
+            colors.push(color);
+            uvs.push([x_percent, y_percent]);
+            if x != resolution - 1 && y != resolution - 1 {
+                // Triangle 1
+                indices.push(i);
+                indices.push(i + resolution + 1);
+                indices.push(i + resolution);
+                // Triangle 2
+                indices.push(i);
+                indices.push(i + 1);
+                indices.push(i + resolution + 1);
+            }
+        }
+    }
+
+    if skirt_depth > 0.0 {
+        let mut border: Vec<u32> = Vec::with_capacity(4 * resolution as usize);
+        border.extend(0..resolution - 1);
+        border.extend((0..resolution - 1).map(|y| y * resolution + resolution - 1));
+        border.extend(
+            (1..resolution)
+                .rev()
+                .map(|x| (resolution - 1) * resolution + x),
+        );
+        border.extend((1..resolution).rev().map(|y| y * resolution));
+
+        let first = positions.len() as u32;
+        for &index in &border {
+            let index = index as usize;
+            let vertex = Vec3::from(positions[index]);
+            let radius = radii[index];
+            positions.push((vertex * (radius - skirt_depth) / radius).to_array());
+            normals.push(normals[index]);
+            uvs.push(uvs[index]);
+            colors.push(colors[index]);
+        }
+        let count = border.len();
+        for i in 0..count {
+            let next = (i + 1) % count;
+            let (top, next_top) = (border[i], border[next]);
+            let (bottom, next_bottom) = (first + i as u32, first + next as u32);
+            // Skirts are double sided, as they are seen from both sides depending on the face
+            indices.extend([top, bottom, next_top, next_top, bottom, next_bottom]);
+            indices.extend([top, next_top, bottom, next_top, next_bottom, bottom]);
+        }
+    }
+
+    MeshData {
+        positions,
+        indices,
+        normals,
+        uvs,
+        colors,
+    }
+}
+
+/// Noise value in the range [0, 1] at `direction` on the unit sphere
+fn surface_noise(planet: &Planet, graph: Option<&Graph>, direction: Vec3) -> f32 {
+    let point = [
+        f64::from(direction.x),
+        f64::from(direction.y),
+        f64::from(direction.z),
+    ];
+    (graph.map_or_else(
+        || {
+            get_noise_at_point_3d(
+                point,
+                planet.seed,
+                planet.scale / 100.0,
+                planet.offset,
+                &planet.method,
+                &planet.function,
+            )
+        },
+        |graph| graph_noise_at_point_3d(point, planet.scale / 100.0, planet.offset, graph),
+    ) as f32
+        + 1.0)
+        * 0.5
+}
+
+/// Distance from the center of the planet for a given noise value
+fn surface_radius(planet: &Planet, noise_value: f32) -> f32 {
+    let height_value = (0_f32.max(noise_value - planet.sea_percent / 100.0)) * 0.2;
+    1.0 + height_value.powf(planet.height_exponent)
+}
+
+/// Vertex color of the surface at `direction`, by biome if `biomes` is set, with the biome ID
+fn surface_color(
+    grad: &colorgrad::Gradient,
+    biomes: Option<&(&Biomes, BiomeSampler)>,
+    noise_value: f32,
+    direction: Vec3,
+) -> ([f32; 4], Option<usize>) {
+    let height = f64::from(noise_value) * 100.0;
+    if let Some((biomes, sampler)) = biomes {
+        let biome = sampler.at_direction(height, direction);
+        let color = biomes
+            .color(biome)
+            .map(|channel| f32::from(channel) / 255.0);
+        return (color, Some(biome));
+    }
+    let color = grad.at(height);
+    (
+        [
+            color.r as f32,
+            color.g as f32,
+            color.b as f32,
+            color.a as f32,
+        ],
+        None,
+    )
+}
+
+/// Normal of the displaced surface at `direction`, using central differences in a tangent basis
+/// that only depends on `direction`, so vertices shared by two cube faces get the same normal
+fn surface_normal(planet: &Planet, graph: Option<&Graph>, direction: Vec3, step: f32) -> Vec3 {
+    let surface = |direction: Vec3| {
+        let direction = direction.normalize();
+        direction * surface_radius(planet, surface_noise(planet, graph, direction))
+    };
+    let (tangent, bitangent) = direction.any_orthonormal_pair();
+    let along_tangent = surface(direction + tangent * step) - surface(direction - tangent * step);
+    let along_bitangent =
+        surface(direction + bitangent * step) - surface(direction - bitangent * step);
+    let normal = along_tangent
+        .cross(along_bitangent)
+        .try_normalize()
+        .unwrap_or(direction);
+    if normal.dot(direction) < 0.0 {
+        -normal
+    } else {
+        normal
+    }
+}
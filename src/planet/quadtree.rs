@@ -0,0 +1,224 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{face_point, Planet, FACES};
+use crate::GenerationStatus;
+
+/// Marks a [`Planet`] as one node of a [`PlanetQuadtree`]
+///
+/// The node covers the patch at `coord` of the cube face `face`, split in `2^level` patches
+/// per side. Every node has the resolution of the planet, so deeper nodes are more detailed
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PlanetNode {
+    /// Index of the cube face, in the order +Y, -Y, +X, -X, +Z, -Z
+    pub face: u8,
+    /// Depth of the node in the quadtree of its face
+    pub level: u32,
+    /// Coordinate of the patch on the face
+    pub coord: UVec2,
+}
+
+impl PlanetNode {
+    /// Direction from the center of the planet to the center of the node
+    #[must_use]
+    pub fn center(&self) -> Vec3 {
+        let patches = (1_u32 << self.level) as f32;
+        face_point(
+            FACES[usize::from(self.face % 6)],
+            [
+                (self.coord.x as f32 + 0.5) / patches,
+                (self.coord.y as f32 + 0.5) / patches,
+            ],
+        )
+    }
+
+    /// Length of the side of the node on the unit cube
+    #[must_use]
+    pub fn size(&self) -> f32 {
+        2.0 / (1_u32 << self.level) as f32
+    }
+
+    /// Returns true if the patches of both nodes overlap, that is if one contains the other
+    pub(crate) fn overlaps(&self, other: &Self) -> bool {
+        let (coarse, fine) = if self.level <= other.level {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        coarse.face == fine.face && fine.coord >> (fine.level - coarse.level) == coarse.coord
+    }
+
+    /// The four nodes covering this node at the next level
+    #[must_use]
+    pub fn children(&self) -> [Self; 4] {
+        [UVec2::ZERO, UVec2::X, UVec2::Y, UVec2::ONE].map(|offset| Self {
+            face: self.face,
+            level: self.level + 1,
+            coord: self.coord * 2 + offset,
+        })
+    }
+}
+
+/// Builds the planet as six quadtrees, one per cube face, that subdivide near `target`
+///
+/// Attach it to an entity with a `SpatialBundle` placing the planet, nodes are spawned as its
+/// children. A node is split while `target` is closer than `split_distance` times its size
+/// to its center, up to `max_level`. Changing the quadtree regenerates every node,
+/// removing it despawns every node
+#[derive(Component, Clone)]
+pub struct PlanetQuadtree {
+    /// Configuration shared by all nodes
+    pub planet: Planet,
+    /// Entity the quadtree subdivides around, such as the camera
+    pub target: Option<Entity>,
+    /// Maximum depth of the quadtrees
+    pub max_level: u32,
+    /// Distance to a node, relative to its size, below which it is split
+    pub split_distance: f32,
+    /// Material of the nodes, shared by all of them and left unchanged by generation
+    pub material: Handle<StandardMaterial>,
+}
+
+/// Spawned nodes of a quadtree
+#[derive(Default)]
+pub struct QuadtreeNodes {
+    nodes: HashMap<PlanetNode, Entity>,
+    /// Replaced nodes, kept until the nodes covering them are generated
+    retired: Vec<(PlanetNode, Entity)>,
+}
+
+impl Default for PlanetQuadtree {
+    fn default() -> Self {
+        Self {
+            planet: Planet::default(),
+            target: None,
+            max_level: 6,
+            split_distance: 1.5,
+            material: Handle::default(),
+        }
+    }
+}
+
+impl PlanetQuadtree {
+    /// Leaf nodes of the quadtrees for a target at `position`, relative to the center of the
+    /// planet. Without a target, only the six faces are returned
+    #[must_use]
+    pub fn leaves(&self, position: Option<Vec3>) -> Vec<PlanetNode> {
+        let mut leaves = vec![];
+        let mut stack: Vec<PlanetNode> = (0..6)
+            .map(|face| PlanetNode { face, ..default() })
+            .collect();
+        while let Some(node) = stack.pop() {
+            let split = node.level < self.max_level
+                && position.is_some_and(|position| {
+                    position.distance(node.center()) < self.split_distance * node.size()
+                });
+            if split {
+                stack.extend(node.children());
+            } else {
+                leaves.push(node);
+            }
+        }
+        leaves
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)]
+pub fn update_planet_quadtrees(
+    mut commands: Commands,
+    quadtrees: Query<(Entity, Ref<PlanetQuadtree>, &GlobalTransform)>,
+    targets: Query<&GlobalTransform>,
+    statuses: Query<&GenerationStatus>,
+    mut removed: RemovedComponents<PlanetQuadtree>,
+    mut spawned: Local<HashMap<Entity, QuadtreeNodes>>,
+) {
+    for entity in removed.read() {
+        if quadtrees.contains(entity) {
+            continue;
+        }
+        let Some(spawned) = spawned.remove(&entity) else {
+            continue;
+        };
+        for node_entity in spawned.nodes.into_values().chain(
+            spawned
+                .retired
+                .into_iter()
+                .map(|(_, node_entity)| node_entity),
+        ) {
+            if let Some(node_entity) = commands.get_entity(node_entity) {
+                node_entity.despawn_recursive();
+            }
+        }
+    }
+    for (entity, quadtree, transform) in &quadtrees {
+        let spawned = spawned.entry(entity).or_default();
+        let position = quadtree
+            .target
+            .and_then(|target| targets.get(target).ok())
+            .map(|target| {
+                transform
+                    .affine()
+                    .inverse()
+                    .transform_point3(target.translation())
+            });
+        let leaves = quadtree.leaves(position);
+
+        let retired = &mut spawned.retired;
+        // Nodes despawned elsewhere are forgotten, and spawned again below if still a leaf
+        spawned.nodes.retain(|node, &mut node_entity| {
+            if commands.get_entity(node_entity).is_none() {
+                return false;
+            }
+            let keep = leaves.contains(node);
+            if !keep {
+                retired.push((*node, node_entity));
+            }
+            keep
+        });
+        let planet = Planet {
+            export: false,
+            ..quadtree.planet.clone()
+        };
+        if quadtree.is_changed() {
+            for &node_entity in spawned.nodes.values() {
+                commands.entity(node_entity).insert(planet.clone());
+            }
+        }
+        for node in leaves {
+            if spawned.nodes.contains_key(&node) {
+                continue;
+            }
+            let node_entity = commands
+                .spawn((
+                    node,
+                    planet.clone(),
+                    PbrBundle {
+                        material: quadtree.material.clone(),
+                        ..default()
+                    },
+                ))
+                .set_parent(entity)
+                .id();
+            spawned.nodes.insert(node, node_entity);
+        }
+
+        // Replaced nodes stay visible until the nodes covering them are generated, to avoid holes
+        let nodes = &spawned.nodes;
+        spawned.retired.retain(|(retired, node_entity)| {
+            let covered =
+                nodes
+                    .iter()
+                    .filter(|(node, _)| node.overlaps(retired))
+                    .all(|(_, &node_entity)| {
+                        statuses
+                            .get(node_entity)
+                            .is_ok_and(|status| *status != GenerationStatus::Pending)
+                    });
+            if covered {
+                if let Some(node_entity) = commands.get_entity(*node_entity) {
+                    node_entity.despawn_recursive();
+                }
+            }
+            !covered
+        });
+    }
+}
@@ -0,0 +1,261 @@
+//! River and lake generation
+//!
+//! Drainage runs on the noise values of a [`Map`](crate::map::Map) or a
+//! [`Terrain`](crate::terrain::Terrain), after erosion. Depressions are filled with a
+//! priority-flood from the sea and the borders of the grid, so that every cell drains towards
+//! them. Cells collecting the flow of enough upstream cells become rivers and are carved into
+//! the heightmap, filled depressions become lakes leveled at the height they spill at.
+//! Cells below the sea percent of the surface are sea, where rivers end.
+//! Tileable noise has no borders, its rivers flow across the edges to the sea
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+/// River and lake configuration
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Rivers {
+    /// Number of cells draining through a cell above which it is part of a river
+    pub threshold: u32,
+    /// Depth in percent river channels are carved into the heightmap
+    pub depth: f64,
+    /// Color rivers are painted with on a [`Map`](crate::map::Map)
+    pub river_color: [u8; 4],
+    /// Color lakes are painted with on a [`Map`](crate::map::Map)
+    pub lake_color: [u8; 4],
+}
+
+impl Default for Rivers {
+    fn default() -> Self {
+        Self {
+            threshold: 500,
+            depth: 2.0,
+            river_color: [50, 100, 200, 255],
+            lake_color: [60, 110, 190, 255],
+        }
+    }
+}
+
+/// Drainage of a grid of noise values, indexed like the noise map
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Drainage {
+    /// Number of cells draining through each cell, including itself
+    pub accumulation: Vec<Vec<u32>>,
+    /// Cells that are part of a river
+    pub rivers: Vec<Vec<bool>>,
+    /// Cells that are part of a lake
+    pub lakes: Vec<Vec<bool>>,
+    /// Rivers as lists of `[x, y]` cells from their source downstream.
+    /// A river ends where it flows into the sea, a lake, the border or another river,
+    /// in which case the last cell is shared with that river
+    pub polylines: Vec<Vec<[usize; 2]>>,
+}
+
+/// Cell of the priority-flood, lowest height first, then first pushed first
+#[derive(PartialEq)]
+struct FloodCell {
+    height: f64,
+    order: usize,
+    index: usize,
+}
+
+impl Eq for FloodCell {}
+
+impl Ord for FloodCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .height
+            .total_cmp(&self.height)
+            .then(other.order.cmp(&self.order))
+    }
+}
+
+impl PartialOrd for FloodCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Rivers {
+    /// Computes the drainage of a grid of noise values in the range [0, 100],
+    /// with cells below `sea_level` in percent being sea.
+    /// If `wrap` is set, the grid repeats in both axes like tileable noise, so water only
+    /// drains into the sea, or into the lowest cell if there is no sea
+    #[must_use]
+    pub fn drainage(&self, noise_values: &[Vec<f64>], sea_level: f64, wrap: bool) -> Drainage {
+        self.flood(noise_values, sea_level, wrap).0
+    }
+
+    /// Computes the drainage with the noise values after filling depressions
+    fn flood(
+        &self,
+        noise_values: &[Vec<f64>],
+        sea_level: f64,
+        wrap: bool,
+    ) -> (Drainage, Vec<Vec<f64>>) {
+        let rows = noise_values.len();
+        let cols = noise_values.first().map_or(0, Vec::len);
+        let count = rows * cols;
+        let height = |index: usize| noise_values[index / cols][index % cols];
+        let neighbors = |index: usize| {
+            let (x, y) = (index / cols, index % cols);
+            [
+                (-1, -1),
+                (-1, 0),
+                (-1, 1),
+                (0, -1),
+                (0, 1),
+                (1, -1),
+                (1, 0),
+                (1, 1),
+            ]
+            .into_iter()
+            .filter_map(move |(dx, dy)| {
+                let (nx, ny) = if wrap {
+                    (
+                        (x + rows).checked_add_signed(dx)? % rows,
+                        (y + cols).checked_add_signed(dy)? % cols,
+                    )
+                } else {
+                    (x.checked_add_signed(dx)?, y.checked_add_signed(dy)?)
+                };
+                (nx < rows && ny < cols && nx * cols + ny != index).then_some(nx * cols + ny)
+            })
+        };
+
+        // Priority-flood from the outlets: each cell drains into the cell it was reached from,
+        // and depressions are raised to the level they spill at
+        let mut filled = vec![0.0; count];
+        let mut visited = vec![false; count];
+        let mut receivers: Vec<Option<usize>> = vec![None; count];
+        let mut flood_order = Vec::with_capacity(count);
+        let mut queue = BinaryHeap::new();
+        let mut pushed = 0;
+        let outlet = |index: usize| {
+            let (x, y) = (index / cols, index % cols);
+            let border = !wrap && (x == 0 || y == 0 || x == rows - 1 || y == cols - 1);
+            border || height(index) < sea_level
+        };
+        let mut outlets: Vec<usize> = (0..count).filter(|&index| outlet(index)).collect();
+        if outlets.is_empty() {
+            // Without borders or sea, everything drains into the lowest cell
+            outlets.extend((0..count).min_by(|&a, &b| height(a).total_cmp(&height(b))));
+        }
+        for index in outlets {
+            visited[index] = true;
+            filled[index] = height(index);
+            queue.push(FloodCell {
+                height: filled[index],
+                order: pushed,
+                index,
+            });
+            pushed += 1;
+        }
+        while let Some(FloodCell { index, .. }) = queue.pop() {
+            flood_order.push(index);
+            for neighbor in neighbors(index) {
+                if visited[neighbor] {
+                    continue;
+                }
+                visited[neighbor] = true;
+                filled[neighbor] = height(neighbor).max(filled[index]);
+                receivers[neighbor] = Some(index);
+                queue.push(FloodCell {
+                    height: filled[neighbor],
+                    order: pushed,
+                    index: neighbor,
+                });
+                pushed += 1;
+            }
+        }
+
+        // Cells are flooded after the cell they drain into, so upstream cells come last
+        let mut accumulation = vec![1_u32; count];
+        for &index in flood_order.iter().rev() {
+            if let Some(receiver) = receivers[index] {
+                accumulation[receiver] += accumulation[index];
+            }
+        }
+        let lakes: Vec<bool> = (0..count)
+            .map(|index| filled[index] > height(index))
+            .collect();
+        let rivers: Vec<bool> = (0..count)
+            .map(|index| {
+                accumulation[index] >= self.threshold && !lakes[index] && height(index) >= sea_level
+            })
+            .collect();
+
+        let mut fed = vec![false; count];
+        for index in (0..count).filter(|&index| rivers[index]) {
+            if let Some(receiver) = receivers[index] {
+                fed[receiver] = true;
+            }
+        }
+        let mut traced = vec![false; count];
+        let mut polylines = vec![];
+        for source in (0..count).filter(|&index| rivers[index] && !fed[index]) {
+            let mut polyline = vec![source];
+            traced[source] = true;
+            let mut current = source;
+            while let Some(receiver) = receivers[current] {
+                polyline.push(receiver);
+                if !rivers[receiver] || traced[receiver] {
+                    break;
+                }
+                traced[receiver] = true;
+                current = receiver;
+            }
+            polylines.push(
+                polyline
+                    .into_iter()
+                    .map(|index| [index / cols, index % cols])
+                    .collect(),
+            );
+        }
+
+        let drainage = Drainage {
+            accumulation: to_grid(&accumulation, cols),
+            rivers: to_grid(&rivers, cols),
+            lakes: to_grid(&lakes, cols),
+            polylines,
+        };
+        (drainage, to_grid(&filled, cols))
+    }
+
+    /// Carves the rivers of `drainage` into a grid of noise values in the range [0, 100],
+    /// and raises lakes to the `filled` noise values
+    fn carve(&self, noise_values: &mut [Vec<f64>], drainage: &Drainage, filled: &[Vec<f64>]) {
+        for ((values, filled), (rivers, lakes)) in noise_values
+            .iter_mut()
+            .zip(filled)
+            .zip(drainage.rivers.iter().zip(&drainage.lakes))
+        {
+            for ((value, &filled), (&river, &lake)) in
+                values.iter_mut().zip(filled).zip(rivers.iter().zip(lakes))
+            {
+                if lake {
+                    *value = filled;
+                } else if river {
+                    *value = (*value - self.depth).max(0.0);
+                }
+            }
+        }
+    }
+}
+
+fn to_grid<T: Clone>(values: &[T], cols: usize) -> Vec<Vec<T>> {
+    values.chunks(cols.max(1)).map(<[T]>::to_vec).collect()
+}
+
+/// Computes the drainage of a grid of noise values, carves its rivers and levels its lakes.
+/// If `wrap` is set, the grid repeats in both axes, like tileable noise
+pub(crate) fn drain(
+    noise_values: &mut [Vec<f64>],
+    rivers: Option<&Rivers>,
+    sea_level: f64,
+    wrap: bool,
+) -> Option<Drainage> {
+    let rivers = rivers?;
+    let (drainage, filled) = rivers.flood(noise_values, sea_level, wrap);
+    rivers.carve(noise_values, &drainage, &filled);
+    Some(drainage)
+}
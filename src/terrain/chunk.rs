@@ -0,0 +1,158 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use super::Terrain;
+
+/// Marks a [`Terrain`] as one chunk of a larger terrain
+///
+/// The chunk covers `size` world units starting at `coord * size`, see [`TerrainChunk::translation`].
+/// Chunks sample the noise in world space, so the edges of adjacent chunks match exactly
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TerrainChunk {
+    /// Coordinate of the chunk in the chunk grid
+    pub coord: IVec2,
+    /// Level of detail, each level halves the resolution of the chunk
+    pub lod: u32,
+}
+
+impl TerrainChunk {
+    /// Translation of the chunk origin for chunks of `size` world units
+    #[must_use]
+    pub const fn translation(&self, size: [u32; 2]) -> Vec3 {
+        Vec3::new(
+            (self.coord.x * size[0] as i32) as f32,
+            0.0,
+            (self.coord.y * size[1] as i32) as f32,
+        )
+    }
+
+    /// Distance between vertices in samples for chunks of `samples` samples per axis.
+    /// Levels of detail that would not evenly divide the chunk are clamped
+    pub(crate) fn step(self, samples: [u32; 2]) -> u32 {
+        let mut lod = self.lod.min(31);
+        while lod > 0 && samples.iter().any(|samples| samples % (1 << lod) != 0) {
+            lod -= 1;
+        }
+        1 << lod
+    }
+}
+
+/// Streams terrain chunks around the entity it is attached to, such as the camera
+///
+/// Chunks within `radius` chunks of the entity are spawned, chunks further away are despawned.
+/// The level of detail of a chunk is the number of `lod_distances` it is at least as far from.
+/// Changing the streamer regenerates every chunk, removing it despawns its chunks.
+/// Chunks are not parented to the streamer, so they stay in place while it moves
+#[derive(Component, Clone)]
+pub struct TerrainStreamer {
+    /// Configuration shared by all chunks, `size` is the size of a chunk
+    pub terrain: Terrain,
+    /// Radius around the tracked entity in chunks
+    pub radius: u32,
+    /// Distances in chunks from which chunks use the next level of detail, in increasing order
+    pub lod_distances: Vec<u32>,
+    /// Material of the chunks, shared by all of them and left unchanged by generation
+    pub material: Handle<StandardMaterial>,
+}
+
+impl Default for TerrainStreamer {
+    fn default() -> Self {
+        Self {
+            terrain: Terrain::default(),
+            radius: 4,
+            lod_distances: vec![2, 3],
+            material: Handle::default(),
+        }
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)]
+pub fn stream_terrain_chunks(
+    mut commands: Commands,
+    streamers: Query<(Entity, Ref<TerrainStreamer>, &GlobalTransform)>,
+    mut removed: RemovedComponents<TerrainStreamer>,
+    mut spawned: Local<HashMap<Entity, HashMap<IVec2, (Entity, u32)>>>,
+) {
+    for streamer_entity in removed.read() {
+        if streamers.contains(streamer_entity) {
+            continue;
+        }
+        for (_, (entity, _)) in spawned.remove(&streamer_entity).unwrap_or_default() {
+            if let Some(entity) = commands.get_entity(entity) {
+                entity.despawn_recursive();
+            }
+        }
+    }
+    for (streamer_entity, streamer, transform) in &streamers {
+        let chunks = spawned.entry(streamer_entity).or_default();
+        let size = streamer.terrain.size.map(|size| size.max(1));
+        let position = transform.translation();
+        let center = IVec2::new(
+            (position.x / size[0] as f32).floor() as i32,
+            (position.z / size[1] as f32).floor() as i32,
+        );
+        let radius = streamer.radius as i32;
+        let in_range = |coord: IVec2| (coord - center).length_squared() <= radius * radius;
+        let lod_distances = &streamer.lod_distances;
+        let lod = |coord: IVec2| {
+            let distance = (coord - center).length_squared();
+            lod_distances
+                .iter()
+                .filter(|&&lod_distance| distance >= (lod_distance * lod_distance) as i32)
+                .count() as u32
+        };
+
+        // Chunks despawned elsewhere are forgotten, and spawned again below if still in range
+        chunks.retain(|&coord, (entity, chunk_lod)| {
+            let Some(mut entity) = commands.get_entity(*entity) else {
+                return false;
+            };
+            if !in_range(coord) {
+                entity.despawn_recursive();
+                return false;
+            }
+            if lod(coord) != *chunk_lod {
+                *chunk_lod = lod(coord);
+                entity.insert(TerrainChunk {
+                    coord,
+                    lod: *chunk_lod,
+                });
+            }
+            true
+        });
+        // Chunks never export, exporting is done on the whole terrain
+        let terrain = Terrain {
+            export: false,
+            export_heightmap: None,
+            ..streamer.terrain.clone()
+        };
+        if streamer.is_changed() {
+            for &(entity, _) in chunks.values() {
+                commands.entity(entity).insert(terrain.clone());
+            }
+        }
+        for x in -radius..=radius {
+            for y in -radius..=radius {
+                let coord = center + IVec2::new(x, y);
+                if !in_range(coord) || chunks.contains_key(&coord) {
+                    continue;
+                }
+                let chunk = TerrainChunk {
+                    coord,
+                    lod: lod(coord),
+                };
+                let entity = commands
+                    .spawn((
+                        chunk,
+                        terrain.clone(),
+                        PbrBundle {
+                            material: streamer.material.clone(),
+                            transform: Transform::from_translation(chunk.translation(size)),
+                            ..default()
+                        },
+                    ))
+                    .id();
+                chunks.insert(coord, (entity, chunk.lod));
+            }
+        }
+    }
+}
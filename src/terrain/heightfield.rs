@@ -0,0 +1,280 @@
+use bevy::{
+    prelude::*,
+    render::mesh::{MeshVertexAttribute, VertexAttributeValues},
+};
+
+use crate::RayHit;
+
+/// Heights of a generated [`Terrain`](super::Terrain), inserted next to it once generated
+///
+/// Positions are in the local space of the terrain entity, as `(x, z)`, except for the
+/// `_world` methods taking the entity's `GlobalTransform`.
+/// Heights are interpolated over the triangle of the mesh under the position,
+/// so they exactly match the rendered surface
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct TerrainHeightfield {
+    /// Number of vertices along x and z
+    pub size: UVec2,
+    /// Position of the first vertex
+    pub origin: Vec2,
+    /// Distance between adjacent vertices
+    pub spacing: f32,
+    /// Vertex heights, row by row along x
+    pub heights: Vec<f32>,
+    /// Vertex normals, laid out like `heights`
+    pub normals: Vec<Vec3>,
+    /// If true, the mesh uses face normals
+    pub flat_shading: bool,
+    /// Lowest vertex height
+    pub min_height: f32,
+    /// Highest vertex height
+    pub max_height: f32,
+}
+
+impl TerrainHeightfield {
+    /// Reads the heights of the `size` grid at the start of `mesh`, before flat shading
+    pub(crate) fn from_mesh(
+        mesh: &Mesh,
+        size: [u32; 2],
+        origin: Vec2,
+        spacing: f32,
+        flat_shading: bool,
+    ) -> Self {
+        let count = (size[0] * size[1]) as usize;
+        let attribute = |attribute: MeshVertexAttribute| match mesh.attribute(attribute) {
+            Some(VertexAttributeValues::Float32x3(values)) => values.get(..count).unwrap_or(&[]),
+            _ => &[],
+        };
+        let heights: Vec<f32> = attribute(Mesh::ATTRIBUTE_POSITION)
+            .iter()
+            .map(|position| position[1])
+            .collect();
+        let (min_height, max_height) = heights.iter().fold(
+            (f32::INFINITY, f32::NEG_INFINITY),
+            |(low, high), &height| (low.min(height), high.max(height)),
+        );
+        Self {
+            size: UVec2::from(size),
+            origin,
+            spacing,
+            heights,
+            normals: attribute(Mesh::ATTRIBUTE_NORMAL)
+                .iter()
+                .map(|&normal| Vec3::from(normal))
+                .collect(),
+            flat_shading,
+            min_height,
+            max_height,
+        }
+    }
+
+    /// Cell under `position` with the position inside it, in the range [0, 1] on both axes
+    fn cell(&self, position: Vec2) -> Option<(UVec2, Vec2)> {
+        if self.size.x < 2 || self.size.y < 2 || self.heights.len() < self.len() {
+            return None;
+        }
+        let grid = (position - self.origin) / self.spacing;
+        let last = (self.size - 1).as_vec2();
+        if grid.cmplt(Vec2::ZERO).any() || grid.cmpgt(last).any() {
+            return None;
+        }
+        let cell = grid.floor().min(last - 1.0);
+        Some((cell.as_uvec2(), grid - cell))
+    }
+
+    const fn len(&self) -> usize {
+        (self.size.x * self.size.y) as usize
+    }
+
+    const fn index(&self, row: u32, col: u32) -> usize {
+        (row * self.size.y + col) as usize
+    }
+
+    /// Vertices of the triangle under `position`, with their barycentric weights.
+    /// Cells are split along the diagonal from `(row, col + 1)` to `(row + 1, col)`, like the mesh
+    fn triangle(&self, position: Vec2) -> Option<[(usize, f32); 3]> {
+        let (cell, Vec2 { x: u, y: v }) = self.cell(position)?;
+        let corner = |row: u32, col: u32| self.index(cell.x + row, cell.y + col);
+        Some(if u + v <= 1.0 {
+            [
+                (corner(0, 0), 1.0 - u - v),
+                (corner(1, 0), u),
+                (corner(0, 1), v),
+            ]
+        } else {
+            [
+                (corner(1, 1), u + v - 1.0),
+                (corner(0, 1), 1.0 - u),
+                (corner(1, 0), 1.0 - v),
+            ]
+        })
+    }
+
+    /// Height of the surface at `position`, or `None` outside the terrain
+    #[must_use]
+    pub fn height_at(&self, position: Vec2) -> Option<f32> {
+        let triangle = self.triangle(position)?;
+        Some(
+            triangle
+                .iter()
+                .map(|&(index, weight)| self.heights[index] * weight)
+                .sum(),
+        )
+    }
+
+    /// Normal of the surface at `position`, or `None` outside the terrain.
+    /// Vertex normals are interpolated like the shading of the mesh,
+    /// or the face normal is returned with flat shading
+    #[must_use]
+    pub fn normal_at(&self, position: Vec2) -> Option<Vec3> {
+        let triangle = self.triangle(position)?;
+        if self.flat_shading || self.normals.len() < self.len() {
+            let [a, b, c] = triangle.map(|(index, _)| {
+                let (row, col) = (index as u32 / self.size.y, index as u32 % self.size.y);
+                Vec3::new(
+                    (row as f32).mul_add(self.spacing, self.origin.x),
+                    self.heights[index],
+                    (col as f32).mul_add(self.spacing, self.origin.y),
+                )
+            });
+            let normal = (c - a).cross(b - a).normalize_or_zero();
+            return Some(if normal.y < 0.0 { -normal } else { normal });
+        }
+        Some(
+            triangle
+                .iter()
+                .map(|&(index, weight)| self.normals[index] * weight)
+                .sum::<Vec3>()
+                .normalize_or_zero(),
+        )
+    }
+
+    /// Height in world space of the surface under `world_xz`, for a terrain entity placed by
+    /// `transform`, or `None` outside the terrain
+    #[must_use]
+    pub fn height_at_world(&self, transform: &GlobalTransform, world_xz: Vec2) -> Option<f32> {
+        let (position, _) = self.surface_under(transform, world_xz)?;
+        Some(transform.transform_point(position).y)
+    }
+
+    /// Normal in world space of the surface under `world_xz`, for a terrain entity placed by
+    /// `transform`, or `None` outside the terrain
+    #[must_use]
+    pub fn normal_at_world(&self, transform: &GlobalTransform, world_xz: Vec2) -> Option<Vec3> {
+        let (_, normal) = self.surface_under(transform, world_xz)?;
+        Some(world_normal(transform, normal))
+    }
+
+    /// First intersection of `ray` in world space with the surface, for a terrain entity placed
+    /// by `transform`, see [`TerrainHeightfield::raycast`]. The hit is in world space
+    #[must_use]
+    pub fn raycast_world(&self, transform: &GlobalTransform, ray: Ray3d) -> Option<RayHit> {
+        let inverse = transform.affine().inverse();
+        let direction = Dir3::new(inverse.transform_vector3(*ray.direction)).ok()?;
+        let hit = self.raycast(Ray3d {
+            origin: inverse.transform_point3(ray.origin),
+            direction,
+        })?;
+        let point = transform.transform_point(hit.point);
+        Some(RayHit {
+            point,
+            normal: world_normal(transform, hit.normal),
+            distance: point.distance(ray.origin),
+        })
+    }
+
+    /// Local position and normal of the surface crossed by the vertical world line through
+    /// `world_xz`. If the terrain is tilted, the line is cast from above the terrain
+    fn surface_under(&self, transform: &GlobalTransform, world_xz: Vec2) -> Option<(Vec3, Vec3)> {
+        let inverse = transform.affine().inverse();
+        let origin = inverse.transform_point3(Vec3::new(world_xz.x, 0.0, world_xz.y));
+        let up = inverse.transform_vector3(Vec3::Y).try_normalize()?;
+        if up.xz().length() <= 1e-6 {
+            let position = origin.xz();
+            let height = self.height_at(position)?;
+            return Some((
+                Vec3::new(position.x, height, position.y),
+                self.normal_at(position)?,
+            ));
+        }
+        let extent = (self.size.as_vec2() * self.spacing).length();
+        let height_range = (self.max_height - self.min_height).max(0.0);
+        let reach = extent + height_range + (origin.xz() - self.origin).length() + origin.y.abs();
+        let ray = Ray3d::new(origin + up * reach, -up);
+        let hit = self.raycast(ray)?;
+        Some((hit.point, hit.normal))
+    }
+
+    /// First intersection of `ray` with the surface, coming from above.
+    /// The ray is marched through the box bounding the terrain and its heights in steps of half
+    /// the vertex spacing, then the crossing is refined by bisection, so features narrower than
+    /// a step can be missed
+    #[must_use]
+    pub fn raycast(&self, ray: Ray3d) -> Option<RayHit> {
+        if self.size.x < 2 || self.size.y < 2 || self.heights.len() < self.len() {
+            return None;
+        }
+        let max = self.origin + (self.size - 1).as_vec2() * self.spacing;
+        let step = self.spacing * 0.5;
+        // Heights are padded by a step, so that flat surfaces are crossed within the box
+        let low = Vec3::new(self.origin.x, self.min_height - step, self.origin.y);
+        let high = Vec3::new(max.x, self.max_height + step, max.y);
+        // Slab intersection of the ray with the bounding box
+        let (mut near, mut far) = (0.0_f32, f32::INFINITY);
+        for axis in 0..3 {
+            let (start, direction) = (ray.origin[axis], ray.direction[axis]);
+            if direction.abs() <= f32::EPSILON {
+                if start < low[axis] || start > high[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let a = (low[axis] - start) / direction;
+            let b = (high[axis] - start) / direction;
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+        }
+        if near > far || !far.is_finite() {
+            return None;
+        }
+
+        // Height of the ray above the surface, positions are clamped against rounding errors
+        let above = |distance: f32| {
+            let point = ray.get_point(distance);
+            let position = Vec2::new(point.x, point.z).clamp(self.origin, max);
+            self.height_at(position).map(|height| point.y - height)
+        };
+        let mut previous = (near, above(near)?);
+        for index in 1..=((far - near) / step).ceil() as u32 {
+            let distance = (index as f32).mul_add(step, near).min(far);
+            let height = above(distance)?;
+            if previous.1 >= 0.0 && height < 0.0 {
+                let (mut low, mut high) = (previous.0, distance);
+                for _ in 0..24 {
+                    let middle = (low + high) * 0.5;
+                    if above(middle)? < 0.0 {
+                        high = middle;
+                    } else {
+                        low = middle;
+                    }
+                }
+                let point = ray.get_point(high);
+                let position = Vec2::new(point.x, point.z).clamp(self.origin, max);
+                return Some(RayHit {
+                    point,
+                    normal: self.normal_at(position)?,
+                    distance: high,
+                });
+            }
+            previous = (distance, height);
+        }
+        None
+    }
+}
+
+/// Normal in world space of a local `normal`, transformed by the inverse transpose of `transform`
+/// so that it stays orthogonal to the surface when scaled unevenly
+fn world_normal(transform: &GlobalTransform, normal: Vec3) -> Vec3 {
+    let inverse = Mat3::from(transform.affine().matrix3).inverse();
+    (inverse.transpose() * normal).normalize_or_zero()
+}
@@ -0,0 +1,799 @@
+//! Generate terrain
+//! # Example
+//! For configuration, see [`Terrain`](struct.Terrain.html)
+//! ```no_run
+//! use bevy::prelude::*;
+//! use bevy_generative::terrain::{TerrainBundle, TerrainPlugin};
+//!
+//! fn main() {
+//!     App::new()
+//!         .add_plugins(DefaultPlugins)
+//!         .add_plugins(TerrainPlugin)
+//!         .add_systems(Startup, setup)
+//!         .run();
+//! }
+//!
+//! fn setup(mut commands: Commands) {
+//!     commands.spawn(PointLightBundle {
+//!         transform: Transform::from_xyz(-2.0, 2.5, 5.0).looking_at(Vec3::ZERO, Vec3::Y),
+//!         ..default()
+//!     });
+//!     commands.spawn(Camera3dBundle {
+//!         transform: Transform::from_xyz(-2.0, 2.5, 5.0).looking_at(Vec3::ZERO, Vec3::Y),
+//!         ..default()
+//!     });
+//!     commands.spawn(TerrainBundle::default());
+//! }
+//! ```
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_asset::RenderAssetUsages, render_resource::PrimitiveTopology},
+};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::{
+    biome::{BiomeSampler, Biomes},
+    erosion::{erode, HydraulicErosion, ThermalErosion},
+    export::{HeightmapFormat, ModelFormat},
+    map::{color_images, generate_images, Map},
+    noise::{generate_gradient, generate_noise_map, generate_noise_map_at, Noise},
+    river::{drain, Drainage, Rivers},
+    util::{
+        export_heightmap, export_model, image_from_buffer, mesh_to_glb, replace_asset, save_bytes,
+        write_bytes, write_model, GenerationTask,
+    },
+    GenerationStatus, RayHit,
+};
+
+mod chunk;
+mod heightfield;
+
+pub use chunk::{TerrainChunk, TerrainStreamer};
+pub use heightfield::TerrainHeightfield;
+
+/// Component for terrain configuration
+#[derive(Component, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Terrain {
+    /// Noise configuration for terrain
+    pub noise: Noise,
+    /// Size of the terrain
+    pub size: [u32; 2],
+    /// Resolution of terrain
+    pub resolution: u32,
+    /// If true, renders terrain mesh as wireframe
+    pub wireframe: bool,
+    /// If true, vertices are not shared between triangles and each triangle uses its face normal.
+    /// Useful for low-poly styles
+    pub flat_shading: bool,
+    /// Height values are raised to this value.
+    /// Lower values result in plains, higher values result in mountains
+    pub height_exponent: f32,
+    /// Percentage of terrain that should appear under sea
+    /// The mesh below this value will be flat
+    pub sea_percent: f32,
+    /// If true, exported models embed the colored noise as a texture instead of using vertex colors
+    pub bake_texture: bool,
+    /// Number of texels per vertex along each axis of the baked texture
+    pub texture_resolution: u32,
+    /// If set, the noise values are eroded before meshing.
+    /// Erosion depends on the whole heightmap, so neither erosion is applied to [`TerrainChunk`]s
+    pub hydraulic_erosion: Option<HydraulicErosion>,
+    /// If set, the noise values are eroded by slope after hydraulic erosion
+    pub thermal_erosion: Option<ThermalErosion>,
+    /// If set, rivers are carved into the noise values after erosion.
+    /// Like erosion, rivers are not applied to [`TerrainChunk`]s
+    pub rivers: Option<Rivers>,
+    /// Depth of the skirts hanging from the borders of [`TerrainChunk`] meshes,
+    /// hiding cracks between chunks at a different level of detail.
+    /// Skirts are this deep per vertex step of the chunk, plus the height range of its border
+    pub skirt_depth: f32,
+    /// If set, the terrain is colored by biome instead of by the gradient.
+    /// Temperature decreases with altitude, see [`Biomes::altitude_cooling`]
+    pub biomes: Option<Biomes>,
+    /// Format used when exporting the model
+    pub export_format: ModelFormat,
+    /// If true, exports model in `export_format`
+    #[serde(skip)]
+    pub export: bool,
+    /// If set, exports the heightmap in the given format.
+    /// Chunks export the heightmap of their own vertices
+    #[serde(skip)]
+    pub export_heightmap: Option<HeightmapFormat>,
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Self {
+            noise: Noise::default(),
+            size: [2; 2],
+            resolution: 15,
+            wireframe: false,
+            flat_shading: false,
+            height_exponent: 1.0,
+            sea_percent: 10.0,
+            bake_texture: false,
+            texture_resolution: 4,
+            hydraulic_erosion: None,
+            thermal_erosion: None,
+            rivers: None,
+            skirt_depth: 1.0,
+            biomes: None,
+            export_format: ModelFormat::default(),
+            export: false,
+            export_heightmap: None,
+        }
+    }
+}
+
+impl Terrain {
+    /// Generates the terrain mesh and encodes it as binary glTF, without opening a file dialog
+    ///
+    /// # Errors
+    /// Returns an error if generation or encoding fails
+    pub fn to_glb_bytes(&self) -> Result<Vec<u8>, String> {
+        let (_, mesh, _) = generate_mesh(self)?;
+        self.glb_bytes(&mesh)
+    }
+
+    fn glb_bytes(&self, mesh: &Mesh) -> Result<Vec<u8>, String> {
+        mesh_to_glb(mesh, self.baked_texture()?.as_ref())
+    }
+
+    fn baked_texture(&self) -> Result<Option<RgbaImage>, String> {
+        self.bake_texture
+            .then(|| generate_texture(self))
+            .transpose()
+    }
+
+    /// Generates the terrain mesh and writes it in `export_format` to `path`, without opening a file dialog.
+    /// Files are named after the file stem of `path`, some formats write several files, see [`ModelFormat`]
+    ///
+    /// # Errors
+    /// Returns an error if generation, encoding or writing the files fails
+    pub fn export_to_path(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let (_, mesh, _) = generate_mesh(self)?;
+        write_model(
+            &mesh,
+            self.baked_texture()?.as_ref(),
+            self.export_format,
+            path.as_ref(),
+        )
+    }
+
+    /// Generates the terrain and returns its heightfield, the way it is inserted on the entity
+    ///
+    /// # Errors
+    /// Returns an error if generation fails
+    pub fn to_heightfield(&self) -> Result<TerrainHeightfield, String> {
+        let (_, _, heightfield) = generate_mesh(self)?;
+        Ok(heightfield)
+    }
+
+    /// Casts the world space `ray` against a generated terrain entity, or a chunk, from its
+    /// [`TerrainHeightfield`] and `GlobalTransform`, without generating it again.
+    /// See [`TerrainHeightfield::raycast_world`]
+    #[must_use]
+    pub fn raycast(
+        heightfield: &TerrainHeightfield,
+        transform: &GlobalTransform,
+        ray: Ray3d,
+    ) -> Option<RayHit> {
+        heightfield.raycast_world(transform, ray)
+    }
+
+    /// Encodes the noise map of the terrain as a heightmap in `format`, without opening a file dialog
+    ///
+    /// # Errors
+    /// Returns an error if encoding fails
+    pub fn to_heightmap_bytes(&self, format: HeightmapFormat) -> Result<Vec<u8>, String> {
+        export_heightmap(&generate_heightmap(self), format)
+    }
+
+    /// Encodes the noise values at the vertices of `chunk` as a heightmap in `format`, so the
+    /// heightmaps of adjacent chunks share their edges
+    ///
+    /// # Errors
+    /// Returns an error if encoding fails
+    pub fn chunk_to_heightmap_bytes(
+        &self,
+        chunk: TerrainChunk,
+        format: HeightmapFormat,
+    ) -> Result<Vec<u8>, String> {
+        let (origin, vertices, step) = chunk_grid(self, chunk);
+        export_heightmap(
+            &generate_noise_map_at(&self.noise, origin, vertices, step),
+            format,
+        )
+    }
+
+    /// Biome ID of every vertex of the terrain, indexed like the noise map.
+    /// Returns `None` if `biomes` is not set
+    #[must_use]
+    pub fn to_biome_ids(&self) -> Option<Vec<Vec<usize>>> {
+        let biomes = self.biomes.as_ref()?;
+        let noise_values = generate_heightmap(self);
+        Some(BiomeSampler::new(biomes, f64::from(self.sea_percent)).grid(
+            &noise_values,
+            heightmap_origin(self),
+            1,
+        ))
+    }
+
+    /// Drainage of the terrain, with its rivers, lakes and flow accumulation.
+    /// Returns `None` if `rivers` is not set
+    #[must_use]
+    pub fn to_drainage(&self) -> Option<Drainage> {
+        generate_drained_heightmap(self).1
+    }
+
+    /// Writes the noise map of the terrain as a heightmap in `format` to `path`, without opening a file dialog
+    ///
+    /// # Errors
+    /// Returns an error if encoding or writing the file fails
+    pub fn export_heightmap_to_path(
+        &self,
+        path: impl AsRef<Path>,
+        format: HeightmapFormat,
+    ) -> Result<(), String> {
+        write_bytes(path.as_ref(), &self.to_heightmap_bytes(format)?)
+    }
+}
+
+/// Render `Terrain` as a `PbrBundle`
+#[derive(Bundle, Default)]
+pub struct TerrainBundle {
+    /// Terrain configuration
+    pub terrain: Terrain,
+    /// Generated mesh data is written to `PbrBundle`
+    pub pbr_bundle: PbrBundle,
+}
+
+/// Plugin to generate terrain.
+/// The terrain is only regenerated when its [`Terrain`] component changes.
+/// Generation runs in the background, see [`GenerationStatus`]
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                chunk::stream_terrain_chunks,
+                spawn_terrain_tasks,
+                apply_terrain_tasks,
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Component)]
+struct TerrainTask(GenerationTask<TerrainOutput>);
+
+struct TerrainOutput {
+    gradient: RgbaImage,
+    mesh: Mesh,
+    heightfield: TerrainHeightfield,
+    export: bool,
+    texture: Option<RgbaImage>,
+    heightmap: Option<(HeightmapFormat, Vec<u8>)>,
+}
+
+fn spawn_terrain_tasks(
+    mut commands: Commands,
+    mut query: Query<
+        (Entity, &mut Terrain, Option<&TerrainChunk>),
+        Or<(Changed<Terrain>, Changed<TerrainChunk>)>,
+    >,
+) {
+    for (entity, mut terrain, chunk) in &mut query {
+        let chunk = chunk.copied();
+        let terrain = terrain.bypass_change_detection();
+        let config = terrain.clone();
+        terrain.export = false;
+        terrain.export_heightmap = None;
+        let task = GenerationTask::spawn(move || {
+            // Chunks sample their noise with an apron, so their heightmap is sampled again
+            let (noise_values, (gradient, mesh, heightfield)) = if let Some(chunk) = chunk {
+                (None, generate_chunk_mesh(&config, chunk)?)
+            } else {
+                let noise_values = generate_heightmap(&config);
+                let output = generate_heightmap_mesh(&config, &noise_values)?;
+                (Some(noise_values), output)
+            };
+            let heightmap = config
+                .export_heightmap
+                .map(|format| {
+                    match (&noise_values, chunk) {
+                        (Some(noise_values), _) => export_heightmap(noise_values, format),
+                        (None, Some(chunk)) => config.chunk_to_heightmap_bytes(chunk, format),
+                        (None, None) => config.to_heightmap_bytes(format),
+                    }
+                    .map(|bytes| (format, bytes))
+                })
+                .transpose()?;
+            let texture = if config.export {
+                config.baked_texture()?
+            } else {
+                None
+            };
+            Ok(TerrainOutput {
+                gradient,
+                mesh,
+                heightfield,
+                export: config.export,
+                heightmap,
+                texture,
+            })
+        });
+        commands
+            .entity(entity)
+            .insert((TerrainTask(task), GenerationStatus::Pending));
+    }
+}
+
+fn apply_terrain_tasks(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut query: Query<(
+        Entity,
+        &mut Terrain,
+        &mut Handle<Mesh>,
+        &Handle<StandardMaterial>,
+        &mut TerrainTask,
+        Has<TerrainChunk>,
+    )>,
+) {
+    for (entity, mut terrain, mut mesh_handle, material, mut task, is_chunk) in &mut query {
+        let Some(result) = task.0.poll() else {
+            continue;
+        };
+        let mut entity = commands.entity(entity);
+        entity.remove::<TerrainTask>();
+        match result {
+            Ok(output) => {
+                // Chunks share the material of their streamer, which is left as configured
+                if !is_chunk {
+                    if let Some(material) = materials.get_mut(material) {
+                        *material = StandardMaterial::default();
+                    }
+                }
+                let terrain = terrain.bypass_change_detection();
+                replace_asset(
+                    &mut images,
+                    &mut terrain.noise.gradient.image,
+                    image_from_buffer(output.gradient),
+                );
+                if output.export {
+                    export_model(
+                        &output.mesh,
+                        output.texture.as_ref(),
+                        terrain.export_format,
+                        "terrain",
+                    );
+                }
+                replace_asset(&mut meshes, &mut mesh_handle, output.mesh);
+                if let Some((format, heightmap)) = output.heightmap {
+                    save_bytes(
+                        &heightmap,
+                        &format!("heightmap.{}", format.extension()),
+                        format.mime_type(),
+                    );
+                }
+                entity.insert((output.heightfield, GenerationStatus::Ready));
+            }
+            Err(error) => {
+                entity.insert(GenerationStatus::Failed(error));
+            }
+        }
+    }
+}
+
+fn generate_heightmap(terrain: &Terrain) -> Vec<Vec<f64>> {
+    generate_drained_heightmap(terrain).0
+}
+
+/// Noise values after erosion and river carving, with the drainage if `rivers` is set
+fn generate_drained_heightmap(terrain: &Terrain) -> (Vec<Vec<f64>>, Option<Drainage>) {
+    let mut noise = terrain.noise.clone();
+    noise.size = [
+        terrain.size[0] * terrain.resolution,
+        terrain.size[1] * terrain.resolution,
+    ];
+    let mut noise_values = generate_noise_map(&noise);
+    erode(
+        &mut noise_values,
+        terrain.hydraulic_erosion.as_ref(),
+        terrain.thermal_erosion.as_ref(),
+        noise.tileable,
+    );
+    let drainage = drain(
+        &mut noise_values,
+        terrain.rivers.as_ref(),
+        f64::from(terrain.sea_percent),
+        noise.tileable,
+    );
+    (noise_values, drainage)
+}
+
+/// Global sample index of the first noise value of the heightmap
+fn heightmap_origin(terrain: &Terrain) -> [i64; 2] {
+    terrain
+        .size
+        .map(|size| -i64::from(size * terrain.resolution / 2))
+}
+
+pub(crate) fn generate_mesh(
+    terrain: &Terrain,
+) -> Result<(RgbaImage, Mesh, TerrainHeightfield), String> {
+    generate_heightmap_mesh(terrain, &generate_heightmap(terrain))
+}
+
+/// Generates the mesh of the terrain from its already generated heightmap
+fn generate_heightmap_mesh(
+    terrain: &Terrain,
+    noise_values: &[Vec<f64>],
+) -> Result<(RgbaImage, Mesh, TerrainHeightfield), String> {
+    let (grad, gradient_buffer) = generate_gradient(
+        &terrain.noise.regions,
+        &terrain.noise.gradient,
+        terrain.noise.base_color,
+    )?;
+    let resolution = terrain.resolution as f32;
+    let width = terrain.size[0] as f32 + 1.0;
+    let depth = terrain.size[1] as f32 + 1.0;
+    let colors = vertex_colors(terrain, &grad, noise_values, heightmap_origin(terrain), 1);
+    let vertices = [
+        terrain.size[0] * terrain.resolution,
+        terrain.size[1] * terrain.resolution,
+    ];
+    let position = |row: f32, col: f32| {
+        [
+            (row / resolution - width / 2.0) + 0.5,
+            (col / resolution - depth / 2.0) + 0.5,
+        ]
+    };
+    let mesh = build_mesh(terrain, noise_values, &colors, vertices, 0, 0.0, position);
+    let (mesh, heightfield) = shade(
+        terrain,
+        mesh,
+        vertices,
+        Vec2::from(position(0.0, 0.0)),
+        1.0 / resolution,
+    );
+    Ok((gradient_buffer, mesh, heightfield))
+}
+
+/// Generates the mesh of `chunk`, with its origin at the corner of the chunk.
+/// The noise is sampled in world space, so the edges of adjacent chunks match exactly.
+/// Chunks at a lower level of detail only use every `2^lod`th sample, and have skirts hiding
+/// the cracks along neighbors at a different level of detail
+pub(crate) fn generate_chunk_mesh(
+    terrain: &Terrain,
+    chunk: TerrainChunk,
+) -> Result<(RgbaImage, Mesh, TerrainHeightfield), String> {
+    let (grad, gradient_buffer) = generate_gradient(
+        &terrain.noise.regions,
+        &terrain.noise.gradient,
+        terrain.noise.base_color,
+    )?;
+    let resolution = terrain.resolution.max(1);
+    let (origin, vertices, step) = chunk_grid(terrain, chunk);
+    // One extra sample on every side, so normals along the edges account for the neighbors
+    let origin = origin.map(|origin| origin - i64::from(step));
+    let noise_values = generate_noise_map_at(
+        &terrain.noise,
+        origin,
+        vertices.map(|vertices| vertices + 2),
+        step,
+    );
+    let spacing = step as f32 / resolution as f32;
+    let colors = vertex_colors(terrain, &grad, &noise_values, origin, step);
+    let mesh = build_mesh(
+        terrain,
+        &noise_values,
+        &colors,
+        vertices,
+        1,
+        terrain.skirt_depth * step as f32,
+        |row, col| [row * spacing, col * spacing],
+    );
+    let (mesh, heightfield) = shade(terrain, mesh, vertices, Vec2::ZERO, spacing);
+    Ok((gradient_buffer, mesh, heightfield))
+}
+
+/// Global sample index of the first vertex of `chunk`, its number of vertices and the number of
+/// samples between them
+fn chunk_grid(terrain: &Terrain, chunk: TerrainChunk) -> ([i64; 2], [u32; 2], u32) {
+    let samples = terrain.size.map(|size| size * terrain.resolution.max(1));
+    let step = chunk.step(samples);
+    let origin = [0, 1].map(|axis| i64::from(chunk.coord[axis]) * i64::from(samples[axis]));
+    (origin, samples.map(|samples| samples / step + 1), step)
+}
+
+/// Reads the heightfield of a grid mesh of `vertices` vertices, then applies flat shading
+fn shade(
+    terrain: &Terrain,
+    mut mesh: Mesh,
+    vertices: [u32; 2],
+    origin: Vec2,
+    spacing: f32,
+) -> (Mesh, TerrainHeightfield) {
+    let flat_shading = terrain.flat_shading && !terrain.wireframe;
+    let heightfield = TerrainHeightfield::from_mesh(&mesh, vertices, origin, spacing, flat_shading);
+    if flat_shading {
+        mesh.duplicate_vertices();
+        mesh.compute_flat_normals();
+    }
+    (mesh, heightfield)
+}
+
+/// Height of the vertex for a noise value
+fn vertex_height(terrain: &Terrain, noise_value: f64) -> f32 {
+    let height_value = (0_f32.max(noise_value as f32 - terrain.sea_percent)) / 100.0;
+    ((height_value * 1.2).powf(terrain.height_exponent) - 0.5) * 2.0
+}
+
+/// Colors of a grid of noise values sampled from `origin` every `step` samples,
+/// by biome if `biomes` is set, else by gradient
+fn vertex_colors(
+    terrain: &Terrain,
+    grad: &colorgrad::Gradient,
+    noise_values: &[Vec<f64>],
+    origin: [i64; 2],
+    step: u32,
+) -> Vec<Vec<[f32; 4]>> {
+    let biomes = terrain.biomes.as_ref().map(|biomes| {
+        let ids = BiomeSampler::new(biomes, f64::from(terrain.sea_percent)).grid(
+            noise_values,
+            origin,
+            step,
+        );
+        (biomes, ids)
+    });
+    noise_values
+        .iter()
+        .enumerate()
+        .map(|(row, values)| {
+            values
+                .iter()
+                .enumerate()
+                .map(|(col, &noise_value)| {
+                    if let Some((biomes, ids)) = &biomes {
+                        return biomes
+                            .color(ids[row][col])
+                            .map(|channel| f32::from(channel) / 255.0);
+                    }
+                    let color = grad.at(noise_value);
+                    [
+                        color.r as f32,
+                        color.g as f32,
+                        color.b as f32,
+                        color.a as f32,
+                    ]
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Builds the grid mesh of `rows` x `cols` vertices from a noise map and its vertex colors.
+/// The noise map has `apron` extra samples on every side, only used to compute normals,
+/// so that the normals of adjacent grids match along their borders.
+/// `position` maps the row and column of a vertex to its horizontal position.
+/// If `skirt_depth` is positive, walls hang from the border, hiding cracks between grids whose
+/// borders do not share all vertices. The walls are `skirt_depth` deeper than the height range
+/// of the border, which bounds how far a coarser neighbor sharing some of its vertices can be
+fn build_mesh(
+    terrain: &Terrain,
+    noise_values: &[Vec<f64>],
+    vertex_colors: &[Vec<[f32; 4]>],
+    [rows, cols]: [u32; 2],
+    apron: u32,
+    skirt_depth: f32,
+    position: impl Fn(f32, f32) -> [f32; 2],
+) -> Mesh {
+    let vertices_count: usize = (rows * cols) as usize;
+    let triangle_count: usize = ((rows - 1) * (cols - 1) * 2 * 3) as usize;
+
+    let mut positions: Vec<[f32; 3]> = Vec::with_capacity(vertices_count);
+    let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(vertices_count);
+    let mut indices: Vec<u32> = Vec::with_capacity(triangle_count);
+    let mut colors: Vec<[f32; 4]> = Vec::with_capacity(vertices_count);
+
+    let apron_rows = rows + 2 * apron;
+    let apron_cols = cols + 2 * apron;
+    let mut apron_positions: Vec<[f32; 3]> = Vec::with_capacity((apron_rows * apron_cols) as usize);
+    for row in 0..apron_rows {
+        for col in 0..apron_cols {
+            let noise_value = noise_values[row as usize][col as usize];
+            let [x, z] = position(row as f32 - apron as f32, col as f32 - apron as f32);
+            apron_positions.push([x, vertex_height(terrain, noise_value), z]);
+        }
+    }
+    let apron_normals = grid_normals(&apron_positions, apron_rows as usize, apron_cols as usize);
+    let mut normals: Vec<[f32; 3]> = Vec::with_capacity(vertices_count);
+
+    let texels = terrain.texture_resolution.max(1) as f32;
+    for row in 0..rows {
+        for col in 0..cols {
+            let apron_index = ((row + apron) * apron_cols + col + apron) as usize;
+            let color = vertex_colors[(row + apron) as usize][(col + apron) as usize];
+
+            positions.push(apron_positions[apron_index]);
+            normals.push(apron_normals[apron_index]);
+            // Each vertex maps to the center of its texel in the baked texture
+            let (row, col) = (row as f32, col as f32);
+            uvs.push([
+                row.mul_add(texels, 0.5) / (rows as f32 * texels),
+                col.mul_add(texels, 0.5) / (cols as f32 * texels),
+            ]);
+            colors.push(color);
+        }
+    }
+
+    for i in 0..(rows - 1) {
+        for j in 0..(cols - 1) {
+            let current = i * cols + j;
+            let next_row = (i + 1) * cols + j;
+
+            // Triangle 1
+            indices.push(current);
+            indices.push(current + 1);
+            indices.push(next_row);
+
+            // Triangle 2
+            indices.push(next_row);
+            indices.push(current + 1);
+            indices.push(next_row + 1);
+        }
+    }
+
+    if skirt_depth > 0.0 {
+        // Border vertices, walked so that the skirts face outwards
+        let mut border: Vec<u32> = Vec::with_capacity(2 * (rows + cols) as usize);
+        border.extend(0..cols - 1);
+        border.extend((0..rows - 1).map(|row| row * cols + cols - 1));
+        border.extend((1..cols).rev().map(|col| (rows - 1) * cols + col));
+        border.extend((1..rows).rev().map(|row| row * cols));
+
+        let (low, high) =
+            border
+                .iter()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), &index| {
+                    let height = positions[index as usize][1];
+                    (low.min(height), high.max(height))
+                });
+        let depth = skirt_depth + high - low;
+        let first = positions.len() as u32;
+        for &index in &border {
+            let [x, y, z] = positions[index as usize];
+            positions.push([x, y - depth, z]);
+            normals.push(normals[index as usize]);
+            uvs.push(uvs[index as usize]);
+            colors.push(colors[index as usize]);
+        }
+        let count = border.len();
+        for i in 0..count {
+            let next = (i + 1) % count;
+            let (top, next_top) = (border[i], border[next]);
+            let (bottom, next_bottom) = (first + i as u32, first + next as u32);
+            indices.extend([top, bottom, next_top, next_top, bottom, next_bottom]);
+        }
+    }
+
+    if terrain.wireframe {
+        let triangle_number = indices.len() / 3;
+        let cloned_indices = indices.clone();
+        indices = vec![];
+        for i in 0..triangle_number {
+            for j in &[0, 1, 1, 2, 2, 0] {
+                indices.push(cloned_indices[i * 3 + j]);
+            }
+        }
+    }
+
+    let mut mesh = if terrain.wireframe {
+        Mesh::new(PrimitiveTopology::LineList, RenderAssetUsages::RENDER_WORLD)
+    } else {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::RENDER_WORLD,
+        )
+    };
+    mesh.insert_indices(Indices::U32(indices));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh
+}
+
+/// Generates the gradient-colored noise covering the terrain, as [`Map`] would,
+/// with `texture_resolution` texels per vertex along each axis
+pub(crate) fn generate_texture(terrain: &Terrain) -> Result<RgbaImage, String> {
+    let texels = terrain.texture_resolution.max(1);
+    let size = [
+        terrain.size[0] * terrain.resolution * texels,
+        terrain.size[1] * terrain.resolution * texels,
+    ];
+    let mut noise = terrain.noise.clone();
+    // Sampling more points over the same area
+    noise.scale *= f64::from(texels);
+    let biomes = terrain.biomes.clone().map(|mut biomes| {
+        biomes.moisture.scale *= f64::from(texels);
+        biomes.temperature.scale *= f64::from(texels);
+        biomes
+    });
+    let map = Map {
+        noise,
+        size,
+        image_size: size,
+        biomes,
+        sea_percent: terrain.sea_percent,
+        ..Default::default()
+    };
+    let (_, image) = if terrain.hydraulic_erosion.is_some()
+        || terrain.thermal_erosion.is_some()
+        || terrain.rivers.is_some()
+    {
+        // Eroded and carved heights can not be sampled at a higher resolution, so they are interpolated
+        color_images(&map, &upsample(&generate_heightmap(terrain), size), None)?
+    } else {
+        generate_images(&map)?
+    };
+    Ok(image)
+}
+
+/// Bilinearly resamples a grid of noise values to `size`, the way the noise is sampled at a higher resolution
+fn upsample(noise_values: &[Vec<f64>], size: [u32; 2]) -> Vec<Vec<f64>> {
+    let rows = noise_values.len();
+    let cols = noise_values.first().map_or(0, Vec::len);
+    let source = |index: u32, target: u32, source: usize| {
+        let position =
+            (f64::from(index) * source as f64 / f64::from(target)).min((source - 1) as f64);
+        let cell = (position as usize).min(source.saturating_sub(2));
+        (cell, position - cell as f64)
+    };
+    (0..size[0])
+        .map(|x| {
+            let (row, u) = source(x, size[0], rows);
+            let next_row = (row + 1).min(rows - 1);
+            (0..size[1])
+                .map(|y| {
+                    let (col, v) = source(y, size[1], cols);
+                    let next_col = (col + 1).min(cols - 1);
+                    let top =
+                        noise_values[row][col].mul_add(1.0 - v, noise_values[row][next_col] * v);
+                    let bottom = noise_values[next_row][col]
+                        .mul_add(1.0 - v, noise_values[next_row][next_col] * v);
+                    top.mul_add(1.0 - u, bottom * u)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Computes smooth normals of a row major grid of positions using central differences.
+/// Border vertices fall back to one sided differences
+fn grid_normals(positions: &[[f32; 3]], rows: usize, cols: usize) -> Vec<[f32; 3]> {
+    let position = |row: usize, col: usize| Vec3::from(positions[row * cols + col]);
+    let mut normals = Vec::with_capacity(positions.len());
+    for row in 0..rows {
+        for col in 0..cols {
+            let along_x =
+                position((row + 1).min(rows - 1), col) - position(row.saturating_sub(1), col);
+            let along_z =
+                position(row, (col + 1).min(cols - 1)) - position(row, col.saturating_sub(1));
+            let normal = along_z.cross(along_x).try_normalize().unwrap_or(Vec3::Y);
+            normals.push(normal.to_array());
+        }
+    }
+    normals
+}
@@ -11,6 +11,10 @@ mod tests {
             offset: [0.0, 0.0],
             method: Method::Perlin,
             function: Function::default(),
+            graph: None,
+            warp: None,
+            tileable: false,
+            falloff: None,
             regions: vec![],
             gradient: Gradient::default(),
             base_color: [255, 255, 255, 255],
@@ -20,6 +24,109 @@ mod tests {
         assert_eq!(noise_map[0].len(), 100);
     }
 
+    #[test]
+    fn test_generate_noise_map_with_graph() {
+        let graph: NoiseNode = serde_json::from_str(
+            r#"{
+                "type": "select",
+                "a": { "type": "constant", "value": -0.5 },
+                "b": {
+                    "type": "scaleBias",
+                    "source": { "type": "fractal", "method": "perlin", "function": { "name": "ridgedMulti" } },
+                    "scale": 0.5,
+                    "bias": 0.5
+                },
+                "control": { "type": "source", "method": "simplex", "seed": 1 },
+                "lower": 0.0,
+                "upper": 1.0,
+                "falloff": 0.2
+            }"#,
+        )
+        .expect("Invalid noise graph");
+        let noise = Noise {
+            size: [100, 100],
+            graph: Some(graph),
+            ..Default::default()
+        };
+        let noise_map = generate_noise_map(&noise);
+        assert_eq!(noise_map.len(), 100);
+        assert_eq!(noise_map[0].len(), 100);
+        assert!(noise_map
+            .iter()
+            .flatten()
+            .all(|v| (0.0..=100.0).contains(v)));
+    }
+
+    #[test]
+    fn test_graph_combiners() {
+        let constant = |value| Box::new(NoiseNode::Constant { value });
+        let graph = NoiseNode::Add {
+            a: constant(0.25),
+            b: Box::new(NoiseNode::Terrace {
+                source: constant(0.3),
+                points: vec![1.0, -1.0, 0.0],
+                invert: false,
+            }),
+        }
+        .build(0);
+        assert!((graph_noise_at_point_3d([0.0; 3], 1.0, [0.0; 3], &graph) - 0.34).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_generate_noise_map_with_warp() {
+        let noise = Noise {
+            size: [50, 50],
+            ..Default::default()
+        };
+        let warped = Noise {
+            size: [50, 50],
+            warp: Some(Warp {
+                strength: 2.0,
+                iterations: 2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let unwarped = Noise {
+            size: [50, 50],
+            warp: Some(Warp {
+                strength: 0.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let noise_map = generate_noise_map(&noise);
+        assert_eq!(generate_noise_map(&unwarped), noise_map);
+        assert_ne!(generate_noise_map(&warped), noise_map);
+    }
+
+    #[test]
+    fn test_generate_tileable_noise_map() {
+        for method in [Method::Perlin, Method::SuperSimplex] {
+            let noise = Noise {
+                size: [40, 60],
+                scale: 10.0,
+                method,
+                tileable: true,
+                ..Default::default()
+            };
+            let noise_map = generate_noise_map(&noise);
+            assert_eq!(noise_map.len(), 40);
+            assert_eq!(noise_map[0].len(), 60);
+
+            // Offsetting by the size of the map wraps around to the same map
+            let wrapped = generate_noise_map(&Noise {
+                offset: [4.0, 6.0],
+                ..noise
+            });
+            for (row, wrapped_row) in noise_map.iter().zip(&wrapped) {
+                for (value, wrapped_value) in row.iter().zip(wrapped_row) {
+                    assert!((value - wrapped_value).abs() < 1e-6);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_generate_noise() {
         let noise_map = generate_noise::<noise::Perlin>([100, 100], 123, 0.1, [0.0, 0.0]);
@@ -80,4 +187,1135 @@ mod tests {
             noise_at_point_3d::<noise::Perlin>([1.0, 2.0, 3.0], 123, 0.1, [0.0, 0.0, 0.0]);
         assert!(noise_value >= -1.0 && noise_value <= 1.0);
     }
+
+    #[test]
+    fn test_generate_map_images() {
+        let map = crate::map::Map {
+            size: [40, 30],
+            image_size: [20, 10],
+            same_size: false,
+            ..Default::default()
+        };
+        let (gradient, image) = crate::map::generate_images(&map).expect("Generation failed");
+        assert_eq!(gradient.dimensions(), (250, 50));
+        assert_eq!(image.dimensions(), (20, 10));
+    }
+
+    #[test]
+    fn test_generate_terrain_mesh() {
+        let terrain = crate::terrain::Terrain {
+            size: [2, 3],
+            resolution: 4,
+            ..Default::default()
+        };
+        let (_, mesh, _) = crate::terrain::generate_mesh(&terrain).expect("Generation failed");
+        assert_eq!(mesh.count_vertices(), 8 * 12);
+    }
+
+    #[test]
+    fn test_generate_planet_mesh() {
+        let planet = crate::planet::Planet {
+            resolution: 4,
+            ..Default::default()
+        };
+        let (_, mesh) = crate::planet::generate_mesh(&planet).expect("Generation failed");
+        assert_eq!(mesh.count_vertices(), 6 * 5 * 5);
+    }
+
+    #[test]
+    fn test_terrain_normals() {
+        use bevy::render::mesh::{Mesh, VertexAttributeValues};
+
+        let terrain = crate::terrain::Terrain {
+            resolution: 4,
+            sea_percent: 0.0,
+            ..Default::default()
+        };
+        let (_, mesh, _) = crate::terrain::generate_mesh(&terrain).expect("Generation failed");
+        let Some(VertexAttributeValues::Float32x3(normals)) =
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+        else {
+            panic!("Terrain mesh has no normals");
+        };
+        assert!(normals.iter().all(|normal| normal[1] > 0.0));
+        assert!(normals.iter().any(|normal| normal[1] < 1.0));
+
+        let terrain = crate::terrain::Terrain {
+            flat_shading: true,
+            ..terrain
+        };
+        let (_, mesh, _) = crate::terrain::generate_mesh(&terrain).expect("Generation failed");
+        assert!(mesh.indices().is_none());
+        assert_eq!(mesh.count_vertices(), 7 * 7 * 2 * 3);
+    }
+
+    #[test]
+    fn test_planet_normals_match_across_seams() {
+        use bevy::{
+            math::Vec3,
+            render::mesh::{Mesh, VertexAttributeValues},
+        };
+
+        let planet = crate::planet::Planet {
+            resolution: 6,
+            sea_percent: 0.0,
+            ..Default::default()
+        };
+        let (_, mesh) = crate::planet::generate_mesh(&planet).expect("Generation failed");
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("Planet mesh has no positions");
+        };
+        let Some(VertexAttributeValues::Float32x3(normals)) =
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+        else {
+            panic!("Planet mesh has no normals");
+        };
+        let mut seams = 0;
+        for (i, position) in positions.iter().enumerate() {
+            for (j, other) in positions.iter().enumerate().skip(i + 1) {
+                if Vec3::from(*position).distance(Vec3::from(*other)) < 1e-5 {
+                    seams += 1;
+                    assert!(Vec3::from(normals[i]).dot(Vec3::from(normals[j])) > 0.999);
+                }
+            }
+        }
+        assert!(seams > 0);
+        assert!(positions
+            .iter()
+            .zip(normals)
+            .any(
+                |(position, normal)| Vec3::from(*position).normalize().dot(Vec3::from(*normal))
+                    < 0.9999
+            ));
+    }
+
+    #[test]
+    fn test_headless_export() {
+        let map = crate::map::Map {
+            size: [20, 10],
+            ..Default::default()
+        };
+        let png = map.to_png_bytes().expect("Export failed");
+        let image = image::load_from_memory(&png).expect("Invalid png");
+        assert_eq!((image.width(), image.height()), (20, 10));
+
+        let terrain = crate::terrain::Terrain {
+            resolution: 4,
+            ..Default::default()
+        };
+        let glb = terrain.to_glb_bytes().expect("Export failed");
+        let gltf = gltf::Gltf::from_slice(&glb).expect("Invalid glb");
+        let primitive = gltf.meshes().next().unwrap().primitives().next().unwrap();
+        assert_eq!(
+            primitive.indices().map(|indices| indices.count()),
+            Some(7 * 7 * 6)
+        );
+        for semantic in [
+            gltf::Semantic::Positions,
+            gltf::Semantic::Normals,
+            gltf::Semantic::TexCoords(0),
+            gltf::Semantic::Colors(0),
+        ] {
+            assert_eq!(
+                primitive.get(&semantic).map(|accessor| accessor.count()),
+                Some(8 * 8)
+            );
+        }
+        assert_eq!(
+            primitive
+                .get(&gltf::Semantic::Colors(0))
+                .unwrap()
+                .dimensions(),
+            gltf::accessor::Dimensions::Vec4
+        );
+        assert!(primitive.material().index().is_some());
+
+        let path = std::env::temp_dir()
+            .join("bevy_generative_export")
+            .join("terrain.glb");
+        terrain.export_to_path(&path).expect("Export failed");
+        assert_eq!(std::fs::read(&path).expect("File not written"), glb);
+    }
+
+    #[test]
+    fn test_export_baked_texture() {
+        let terrain = crate::terrain::Terrain {
+            resolution: 4,
+            bake_texture: true,
+            texture_resolution: 3,
+            ..Default::default()
+        };
+        let glb = terrain.to_glb_bytes().expect("Export failed");
+        let (document, buffers, images) = gltf::import_slice(&glb).expect("Invalid glb");
+        assert_eq!(images.len(), 1);
+        assert_eq!((images[0].width, images[0].height), (24, 24));
+        // The image view holds the PNG only, without padding
+        let gltf::image::Source::View { view, .. } = document.images().next().unwrap().source()
+        else {
+            panic!("Image is not embedded");
+        };
+        let png = &buffers[0][view.offset()..view.offset() + view.length()];
+        assert!(png.ends_with(b"IEND\xAE\x42\x60\x82"));
+        let primitive = document
+            .meshes()
+            .next()
+            .unwrap()
+            .primitives()
+            .next()
+            .unwrap();
+        assert!(primitive.get(&gltf::Semantic::Colors(0)).is_none());
+        assert!(primitive
+            .material()
+            .pbr_metallic_roughness()
+            .base_color_texture()
+            .is_some());
+    }
+
+    #[test]
+    fn test_export_standard_gltf() {
+        use crate::export::ModelFormat;
+
+        let directory = std::env::temp_dir().join("bevy_generative_gltf");
+        let planet = crate::planet::Planet {
+            resolution: 4,
+            export_format: ModelFormat::Gltf,
+            ..Default::default()
+        };
+        planet
+            .export_to_path(directory.join("planet.glb"))
+            .expect("Export failed");
+        let (document, buffers, _) =
+            gltf::import(directory.join("planet.gltf")).expect("Invalid gltf");
+        assert!(matches!(
+            document.buffers().next().unwrap().source(),
+            gltf::buffer::Source::Uri("planet.bin")
+        ));
+        assert!(directory.join("planet.bin").exists());
+        assert_eq!(buffers.len(), 1);
+
+        let planet = crate::planet::Planet {
+            export_format: ModelFormat::GltfEmbedded,
+            ..planet
+        };
+        planet
+            .export_to_path(directory.join("embedded.gltf"))
+            .expect("Export failed");
+        let (_, buffers, _) = gltf::import(directory.join("embedded.gltf")).expect("Invalid gltf");
+        assert_eq!(buffers.len(), 1);
+    }
+
+    #[test]
+    fn test_export_obj_ply_stl() {
+        use crate::export::ModelFormat;
+
+        let directory = std::env::temp_dir().join("bevy_generative_models");
+        let terrain = crate::terrain::Terrain {
+            resolution: 4,
+            bake_texture: true,
+            ..Default::default()
+        };
+        let triangles = 7 * 7 * 2;
+
+        let terrain = crate::terrain::Terrain {
+            export_format: ModelFormat::Obj,
+            ..terrain
+        };
+        terrain
+            .export_to_path(directory.join("terrain.obj"))
+            .expect("Export failed");
+        let obj = std::fs::read_to_string(directory.join("terrain.obj")).expect("No obj");
+        assert_eq!(
+            obj.lines().filter(|line| line.starts_with("v ")).count(),
+            64
+        );
+        assert_eq!(
+            obj.lines().filter(|line| line.starts_with("f ")).count(),
+            triangles
+        );
+        let mtl = std::fs::read_to_string(directory.join("terrain.mtl")).expect("No mtl");
+        assert!(mtl.contains("map_Kd terrain.png"));
+        assert!(directory.join("terrain.png").exists());
+
+        let terrain = crate::terrain::Terrain {
+            export_format: ModelFormat::Ply,
+            ..terrain
+        };
+        terrain
+            .export_to_path(directory.join("terrain.ply"))
+            .expect("Export failed");
+        let ply = std::fs::read(directory.join("terrain.ply")).expect("No ply");
+        let header_end = b"end_header\n";
+        let header_length = ply
+            .windows(header_end.len())
+            .position(|window| window == header_end)
+            .expect("No header")
+            + header_end.len();
+        assert_eq!(ply.len(), header_length + 64 * 28 + triangles * 13);
+
+        let terrain = crate::terrain::Terrain {
+            export_format: ModelFormat::Stl,
+            ..terrain
+        };
+        terrain
+            .export_to_path(directory.join("terrain.stl"))
+            .expect("Export failed");
+        let stl = std::fs::read(directory.join("terrain.stl")).expect("No stl");
+        assert_eq!(stl.len(), 84 + triangles * 50);
+
+        let terrain = crate::terrain::Terrain {
+            wireframe: true,
+            ..terrain
+        };
+        assert!(terrain
+            .export_to_path(directory.join("wireframe.stl"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_export_heightmap() {
+        use crate::export::HeightmapFormat;
+
+        let map = crate::map::Map {
+            size: [20, 10],
+            ..Default::default()
+        };
+        let png = map
+            .to_heightmap_bytes(HeightmapFormat::Png16)
+            .expect("Export failed");
+        let image = image::load_from_memory(&png).expect("Invalid png");
+        assert_eq!(image.color(), image::ColorType::L16);
+        assert_eq!((image.width(), image.height()), (20, 10));
+
+        let tiff = map
+            .to_heightmap_bytes(HeightmapFormat::Tiff32)
+            .expect("Export failed");
+        let mut decoder =
+            tiff::decoder::Decoder::new(std::io::Cursor::new(tiff)).expect("Invalid tiff");
+        assert_eq!(decoder.dimensions().expect("Invalid tiff"), (20, 10));
+        let tiff::decoder::DecodingResult::F32(heights) =
+            decoder.read_image().expect("Invalid tiff")
+        else {
+            panic!("Heightmap is not 32-bit float");
+        };
+        assert!(heights.iter().all(|height| (0.0..=1.0).contains(height)));
+
+        let raw = map
+            .to_heightmap_bytes(HeightmapFormat::R16)
+            .expect("Export failed");
+        assert_eq!(raw.len(), 20 * 10 * 2);
+        let first = u16::from_le_bytes([raw[0], raw[1]]);
+        let expected = generate_noise_map(&Noise {
+            size: [20, 10],
+            ..Default::default()
+        })[0][0];
+        assert_eq!(first, (expected / 100.0 * 65535.0).round() as u16);
+
+        let terrain = crate::terrain::Terrain {
+            resolution: 4,
+            ..Default::default()
+        };
+        let raw = terrain
+            .to_heightmap_bytes(HeightmapFormat::R16)
+            .expect("Export failed");
+        assert_eq!(raw.len(), 8 * 8 * 2);
+
+        // Chunks export the noise values at their own vertices
+        let chunk = |lod| crate::terrain::TerrainChunk {
+            coord: bevy::math::IVec2::new(1, 0),
+            lod,
+        };
+        let raw = terrain
+            .chunk_to_heightmap_bytes(chunk(0), HeightmapFormat::R16)
+            .expect("Export failed");
+        assert_eq!(raw.len(), 9 * 9 * 2);
+        let expected = generate_noise_map_at(&terrain.noise, [8, 0], [1, 1], 1)[0][0];
+        assert_eq!(
+            u16::from_le_bytes([raw[0], raw[1]]),
+            (expected / 100.0 * 65535.0).round() as u16
+        );
+        let png = terrain
+            .chunk_to_heightmap_bytes(chunk(1), HeightmapFormat::Png16)
+            .expect("Export failed");
+        let image = image::load_from_memory(&png).expect("Invalid png");
+        assert_eq!((image.width(), image.height()), (5, 5));
+    }
+
+    #[test]
+    fn test_normal_map() {
+        let map = crate::map::Map {
+            size: [20, 10],
+            ..Default::default()
+        };
+        assert!(map.to_normal_map_png_bytes().is_err());
+
+        let map = crate::map::Map {
+            normal_map: Some(crate::map::NormalMap::default()),
+            ..map
+        };
+        let png = map.to_normal_map_png_bytes().expect("Export failed");
+        let image = image::load_from_memory(&png)
+            .expect("Invalid png")
+            .to_rgb8();
+        assert_eq!(image.dimensions(), (20, 10));
+        // Normals point out of the surface
+        assert!(image.pixels().all(|pixel| pixel[2] >= 128));
+        assert!(image
+            .pixels()
+            .any(|pixel| pixel[0] != 128 || pixel[1] != 128));
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_terrain_chunks_match_at_edges() {
+        use bevy::{
+            math::IVec2,
+            render::mesh::{Mesh, VertexAttributeValues},
+        };
+
+        let noise = Noise {
+            size: [20, 20],
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_noise_map_at(&noise, [-10, -10], [20, 20], 1),
+            generate_noise_map(&noise)
+        );
+
+        let terrain = crate::terrain::Terrain {
+            resolution: 3,
+            sea_percent: 0.0,
+            ..Default::default()
+        };
+        let attributes = |coord: IVec2| {
+            let (_, mesh, _) = crate::terrain::generate_chunk_mesh(
+                &terrain,
+                crate::terrain::TerrainChunk { coord, lod: 0 },
+            )
+            .expect("Generation failed");
+            let Some(VertexAttributeValues::Float32x3(positions)) =
+                mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+            else {
+                panic!("Chunk mesh has no positions");
+            };
+            let Some(VertexAttributeValues::Float32x3(normals)) =
+                mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+            else {
+                panic!("Chunk mesh has no normals");
+            };
+            (positions.clone(), normals.clone())
+        };
+        let vertices = (terrain.size[0] * terrain.resolution + 1) as usize;
+        let (positions, normals) = attributes(IVec2::new(0, 0));
+        let (next_positions, next_normals) = attributes(IVec2::new(1, 0));
+        assert_eq!(positions.len(), vertices * vertices + 4 * (vertices - 1));
+        for col in 0..vertices {
+            let last = (vertices - 1) * vertices + col;
+            assert_eq!(positions[last][0], terrain.size[0] as f32);
+            assert_eq!(next_positions[col][0], 0.0);
+            assert_eq!(positions[last][1], next_positions[col][1]);
+            for axis in 0..3 {
+                assert!((normals[last][axis] - next_normals[col][axis]).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_terrain_chunk_lod() {
+        use crate::terrain::{generate_chunk_mesh, Terrain, TerrainChunk};
+        use bevy::{
+            math::IVec2,
+            render::mesh::{Mesh, VertexAttributeValues},
+        };
+
+        let terrain = Terrain {
+            resolution: 4,
+            sea_percent: 0.0,
+            ..Default::default()
+        };
+        let positions = |lod: u32| {
+            let chunk = TerrainChunk {
+                coord: IVec2::new(1, -1),
+                lod,
+            };
+            let (_, mesh, _) = generate_chunk_mesh(&terrain, chunk).expect("Generation failed");
+            let Some(VertexAttributeValues::Float32x3(positions)) =
+                mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+            else {
+                panic!("Chunk mesh has no positions");
+            };
+            positions.clone()
+        };
+        let fine = positions(0);
+        let coarse = positions(1);
+        // 9 x 9 and 5 x 5 grids, plus one skirt vertex per border vertex
+        assert_eq!(fine.len(), 9 * 9 + 32);
+        assert_eq!(coarse.len(), 5 * 5 + 16);
+        // Coarse vertices land exactly on every other fine vertex
+        for row in 0..5 {
+            for col in 0..5 {
+                assert_eq!(coarse[row * 5 + col], fine[row * 2 * 9 + col * 2]);
+            }
+        }
+        // Skirts hang below the border by the height range of the border, plus the skirt depth
+        // per vertex step
+        let border = (0..25)
+            .filter(|index| ![1, 2, 3].contains(&(index / 5)) || [0, 4].contains(&(index % 5)));
+        let low = border
+            .clone()
+            .map(|index| coarse[index][1])
+            .fold(f32::INFINITY, f32::min);
+        let high = border
+            .map(|index| coarse[index][1])
+            .fold(f32::NEG_INFINITY, f32::max);
+        let depth = terrain.skirt_depth.mul_add(2.0, high - low);
+        assert!((coarse[25][1] - (coarse[0][1] - depth)).abs() < 1e-5);
+        assert!(high > low);
+        // Levels of detail that do not divide the chunk are clamped
+        assert_eq!(positions(5).len(), 2 * 2 + 4);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_planet_quadtree() {
+        use crate::planet::{generate_node_mesh, PlanetNode, PlanetQuadtree};
+        use bevy::{
+            math::{UVec2, Vec3},
+            render::mesh::{Mesh, VertexAttributeValues},
+        };
+
+        let quadtree = PlanetQuadtree {
+            max_level: 4,
+            ..Default::default()
+        };
+        assert_eq!(quadtree.leaves(None).len(), 6);
+        assert_eq!(quadtree.leaves(Some(Vec3::splat(100.0))).len(), 6);
+        // Leaves near the surface are split down to the maximum level and cover every face
+        let leaves = quadtree.leaves(Some(Vec3::Y * 1.01));
+        assert!(leaves.iter().any(|leaf| leaf.level == 4));
+        let area: f32 = leaves
+            .iter()
+            .map(|leaf| 1.0 / (1_u32 << (2 * leaf.level)) as f32)
+            .sum();
+        assert!((area - 6.0).abs() < 1e-6);
+
+        let planet = crate::planet::Planet {
+            resolution: 4,
+            ..Default::default()
+        };
+        let positions = |node: PlanetNode| {
+            let (_, mesh) = generate_node_mesh(&planet, node).expect("Generation failed");
+            let Some(VertexAttributeValues::Float32x3(positions)) =
+                mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+            else {
+                panic!("Node mesh has no positions");
+            };
+            positions.clone()
+        };
+        let node = PlanetNode {
+            face: 2,
+            level: 2,
+            coord: UVec2::new(1, 1),
+        };
+        let next = PlanetNode {
+            coord: UVec2::new(2, 1),
+            ..node
+        };
+        // Retired nodes are removed once the nodes overlapping them are generated
+        assert!(node.overlaps(&node.children()[3].children()[0]));
+        assert!(PlanetNode {
+            face: 2,
+            ..Default::default()
+        }
+        .overlaps(&node));
+        assert!(!node.overlaps(&next));
+        assert!(!node.overlaps(&PlanetNode { face: 3, ..node }));
+        let (node, next) = (positions(node), positions(next));
+        // 5 x 5 vertices plus one skirt vertex per border vertex
+        assert_eq!(node.len(), 5 * 5 + 16);
+        for y in 0..5 {
+            assert_eq!(node[y * 5 + 4], next[y * 5]);
+        }
+    }
+
+    #[test]
+    fn test_hydraulic_erosion() {
+        use crate::{erosion::HydraulicErosion, map::Map, terrain::Terrain};
+
+        let map = Map {
+            size: [64, 64],
+            ..Default::default()
+        };
+        let eroded_map = Map {
+            hydraulic_erosion: Some(HydraulicErosion {
+                droplets: 2000,
+                ..Default::default()
+            }),
+            ..map.clone()
+        };
+        let raw = crate::map::generate_images(&map)
+            .expect("Generation failed")
+            .1;
+        let eroded = crate::map::generate_images(&eroded_map)
+            .expect("Generation failed")
+            .1;
+        assert_ne!(raw, eroded);
+        // Erosion is reproducible from the seed
+        let again = crate::map::generate_images(&eroded_map)
+            .expect("Generation failed")
+            .1;
+        assert_eq!(eroded, again);
+        let reseeded = Map {
+            hydraulic_erosion: Some(HydraulicErosion {
+                droplets: 2000,
+                seed: 1,
+                ..Default::default()
+            }),
+            ..map
+        };
+        let reseeded = crate::map::generate_images(&reseeded)
+            .expect("Generation failed")
+            .1;
+        assert_ne!(eroded, reseeded);
+        // Tileable maps are eroded across their edges
+        let tileable = Map {
+            noise: Noise {
+                tileable: true,
+                ..Default::default()
+            },
+            ..eroded_map
+        };
+        assert!(crate::map::generate_images(&tileable).is_ok());
+
+        let terrain = Terrain {
+            hydraulic_erosion: Some(HydraulicErosion::default()),
+            bake_texture: true,
+            ..Default::default()
+        };
+        assert_ne!(
+            terrain.to_heightmap_bytes(crate::export::HeightmapFormat::Png16),
+            Terrain::default().to_heightmap_bytes(crate::export::HeightmapFormat::Png16)
+        );
+        assert!(terrain.to_glb_bytes().is_ok());
+    }
+
+    #[test]
+    fn test_thermal_erosion() {
+        use crate::{erosion::ThermalErosion, map::Map, terrain::Terrain};
+
+        let slope = |values: &[Vec<f64>]| {
+            values
+                .windows(2)
+                .flat_map(|rows| rows[0].iter().zip(&rows[1]).map(|(a, b)| (a - b).abs()))
+                .fold(0.0, f64::max)
+        };
+        let mut values: Vec<Vec<f64>> = (0..32)
+            .map(|x| {
+                (0..32)
+                    .map(|y| {
+                        if x < 16 {
+                            30.0
+                        } else {
+                            f64::from(y).mul_add(0.1, 20.0)
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let total: f64 = values.iter().flatten().sum();
+        let erosion = ThermalErosion {
+            iterations: 200,
+            talus_angle: 45.0,
+            strength: 0.5,
+        };
+        let mut again = values.clone();
+        erosion.erode(&mut values, false);
+        erosion.erode(&mut again, false);
+        assert_eq!(values, again);
+        assert!(slope(&values) < 2.0);
+        assert!((values.iter().flatten().sum::<f64>() - total).abs() < 1e-6);
+
+        // Wrapped erosion treats the edges like any other cells, so rolling the grid
+        // rolls the result
+        let roll = |values: &[Vec<f64>]| {
+            let mut rolled = values.to_vec();
+            rolled.rotate_left(5);
+            for row in &mut rolled {
+                row.rotate_left(3);
+            }
+            rolled
+        };
+        let (mut wrapped, mut rolled) = (again.clone(), roll(&again));
+        erosion.erode(&mut wrapped, true);
+        erosion.erode(&mut rolled, true);
+        for (row, rolled_row) in roll(&wrapped).iter().zip(&rolled) {
+            for (value, rolled_value) in row.iter().zip(rolled_row) {
+                assert!((value - rolled_value).abs() < 1e-9);
+            }
+        }
+
+        let map = Map {
+            size: [64, 64],
+            thermal_erosion: Some(ThermalErosion::default()),
+            ..Default::default()
+        };
+        assert!(map.to_png_bytes().is_ok());
+        let terrain = Terrain {
+            thermal_erosion: Some(ThermalErosion::default()),
+            ..Default::default()
+        };
+        assert!(terrain.to_glb_bytes().is_ok());
+    }
+
+    #[test]
+    fn test_falloff() {
+        let noise = Noise {
+            size: [64, 64],
+            ..Default::default()
+        };
+        let raw = generate_noise_map(&noise);
+        for shape in [FalloffShape::Radial, FalloffShape::Square] {
+            let island = generate_noise_map(&Noise {
+                falloff: Some(Falloff {
+                    shape,
+                    ..Default::default()
+                }),
+                ..noise.clone()
+            });
+            // Edges are fully lowered, the center is kept
+            assert!(island[0].iter().all(|&value| value < 1e-9));
+            assert!(island.iter().all(|row| row[63] < 1e-9));
+            assert!((island[32][32] - raw[32][32]).abs() < 1e-9);
+        }
+
+        let subtracted = generate_noise_map(&Noise {
+            falloff: Some(Falloff {
+                curve: vec![[0.0, 1.0], [1.0, 0.5]],
+                mode: FalloffMode::Subtract,
+                ..Default::default()
+            }),
+            ..noise.clone()
+        });
+        assert!((subtracted[0][32] - (raw[0][32] - 50.0).max(0.0)).abs() < 1e-9);
+        // Curve points are sorted by distance
+        let unsorted = generate_noise_map(&Noise {
+            falloff: Some(Falloff {
+                curve: vec![[1.0, 0.5], [0.0, 1.0]],
+                mode: FalloffMode::Subtract,
+                ..Default::default()
+            }),
+            ..noise.clone()
+        });
+        assert_eq!(unsorted, subtracted);
+
+        let mut image = image::GrayImage::new(2, 1);
+        image.put_pixel(1, 0, image::Luma([255]));
+        let masked = generate_noise_map(&Noise {
+            falloff: Some(Falloff {
+                image: Some(image.clone()),
+                ..Default::default()
+            }),
+            ..noise
+        });
+        assert!(masked[0].iter().all(|&value| value < 1e-9));
+        assert!((masked[63][0] - raw[63][0]).abs() < 1e-9);
+
+        // Image masks survive a round trip through JSON
+        let falloff = Falloff {
+            image: Some(image),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&falloff).expect("Falloff serialization failed");
+        let parsed: Falloff = serde_json::from_str(&json).expect("Invalid falloff");
+        assert_eq!(parsed.image, falloff.image);
+        assert!(serde_json::from_str::<Falloff>(r#"{ "image": "not a png" }"#).is_err());
+        let parsed: Falloff = serde_json::from_str("{}").expect("Invalid falloff");
+        assert!(parsed.image.is_none());
+    }
+
+    #[test]
+    fn test_biomes() {
+        use crate::biome::Biomes;
+        use crate::map::Map;
+        use crate::planet::Planet;
+        use crate::terrain::Terrain;
+
+        let biomes = Biomes::default();
+        assert_eq!(biomes.classify(10.0, 40.0, 0.9, 0.9), biomes.sea_biome);
+        assert_ne!(biomes.classify(10.0, 5.0, 0.9, 0.9), biomes.sea_biome);
+        // Hot and dry lowlands are deserts, hot and wet lowlands are rainforests
+        assert_eq!(
+            biomes.biomes[biomes.classify(41.0, 40.0, 0.0, 0.99)].name,
+            "Desert"
+        );
+        assert_eq!(
+            biomes.biomes[biomes.classify(41.0, 40.0, 0.99, 0.99)].name,
+            "Tropical rainforest"
+        );
+        // The same climate is colder at the top of a mountain
+        assert_eq!(
+            biomes.biomes[biomes.classify(100.0, 40.0, 0.99, 0.6)].name,
+            "Snow"
+        );
+
+        let map = Map {
+            size: [32, 16],
+            biomes: Some(biomes.clone()),
+            ..Default::default()
+        };
+        let ids = map.to_biome_ids().unwrap();
+        assert_eq!([ids.len(), ids[0].len()], [32, 16]);
+        assert!(ids.iter().flatten().all(|&id| id < biomes.biomes.len()));
+        let image = image::load_from_memory(&map.to_png_bytes().unwrap())
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(image.get_pixel(3, 5).0, biomes.color(ids[3][5]));
+        assert!(Map::default().to_biome_ids().is_none());
+
+        let terrain = Terrain {
+            biomes: Some(biomes.clone()),
+            ..Default::default()
+        };
+        let ids = terrain.to_biome_ids().unwrap();
+        assert_eq!(ids.len() as u32, terrain.size[0] * terrain.resolution);
+        // The baked texture uses the sea level of the terrain, like the vertex colors
+        let terrain = Terrain {
+            sea_percent: 25.0,
+            texture_resolution: 1,
+            ..terrain
+        };
+        let ids = terrain.to_biome_ids().unwrap();
+        let sea_ids = Terrain {
+            sea_percent: 40.0,
+            ..terrain.clone()
+        }
+        .to_biome_ids()
+        .unwrap();
+        assert_ne!(ids, sea_ids);
+        let texture = crate::terrain::generate_texture(&terrain).expect("Generation failed");
+        for (x, y, pixel) in texture.enumerate_pixels() {
+            assert_eq!(pixel.0, biomes.color(ids[x as usize][y as usize]));
+        }
+        assert!(terrain.to_glb_bytes().is_ok());
+        assert!(Planet {
+            biomes: Some(biomes),
+            ..Default::default()
+        }
+        .to_glb_bytes()
+        .is_ok());
+    }
+
+    #[test]
+    fn test_rivers() {
+        use crate::river::{drain, Rivers};
+
+        // A valley sloping down to the x = 0 border, with a pit in its middle
+        let mut noise_values: Vec<Vec<f64>> = (0..20)
+            .map(|x| {
+                (0..21)
+                    .map(|y| {
+                        f64::from(y - 10)
+                            .abs()
+                            .mul_add(3.0, f64::from(x).mul_add(2.0, 50.0))
+                    })
+                    .collect()
+            })
+            .collect();
+        noise_values[10][10] -= 10.0;
+        let original = noise_values.clone();
+        let rivers = Rivers {
+            threshold: 20,
+            ..Default::default()
+        };
+        let drainage = drain(&mut noise_values, Some(&rivers), 40.0, false).unwrap();
+
+        assert!(drainage.lakes[10][10]);
+        assert!(!drainage.rivers[10][10]);
+        assert!(drainage.rivers[1][10] && drainage.rivers[5][10]);
+        assert!(!drainage.rivers[5][2]);
+        assert!(drainage.accumulation[1][10] > drainage.accumulation[5][10]);
+        assert!((noise_values[5][10] - (original[5][10] - rivers.depth)).abs() < 1e-9);
+        assert!((noise_values[5][2] - original[5][2]).abs() < 1e-9);
+        // The pit is filled up to the level it spills at
+        assert!(noise_values[10][10] > original[10][10]);
+        assert!(noise_values[10][10] <= original[9][10] + 1e-9);
+
+        assert!(!drainage.polylines.is_empty());
+        for polyline in &drainage.polylines {
+            assert!(drainage.rivers[polyline[0][0]][polyline[0][1]]);
+            for pair in polyline.windows(2) {
+                assert!(
+                    pair[0][0].abs_diff(pair[1][0]) <= 1 && pair[0][1].abs_diff(pair[1][1]) <= 1
+                );
+            }
+        }
+
+        let map = crate::map::Map {
+            size: [64, 64],
+            rivers: Some(Rivers {
+                threshold: 50,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let drainage = map.to_drainage().unwrap();
+        assert_eq!(drainage.rivers.len(), 64);
+
+        // Without borders or sea, tileable maps drain into their lowest cell
+        let mut noise_values = original;
+        let drainage = drain(&mut noise_values, Some(&rivers), 0.0, true).unwrap();
+        let total: u32 = drainage
+            .accumulation
+            .iter()
+            .flatten()
+            .copied()
+            .max()
+            .unwrap();
+        assert_eq!(total, 20 * 21);
+        assert!(crate::map::Map {
+            noise: Noise {
+                tileable: true,
+                ..Default::default()
+            },
+            ..map.clone()
+        }
+        .to_drainage()
+        .is_some());
+        assert!(map.to_png_bytes().is_ok());
+        assert!(crate::map::Map::default().to_drainage().is_none());
+    }
+
+    #[test]
+    fn test_terrain_heightfield() {
+        use crate::terrain::Terrain;
+        use bevy::{
+            math::{Quat, Vec2, Vec3, Vec3Swizzles},
+            render::mesh::{Mesh, VertexAttributeValues},
+            transform::components::{GlobalTransform, Transform},
+        };
+
+        let terrain = Terrain {
+            size: [4, 3],
+            resolution: 4,
+            ..Default::default()
+        };
+        let (_, mesh, heightfield) =
+            crate::terrain::generate_mesh(&terrain).expect("Generation failed");
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("Missing positions");
+        };
+        let indices: Vec<usize> = mesh.indices().unwrap().iter().collect();
+        let point = |position: [f32; 3]| Vec3::from(position);
+
+        // Heights match the mesh triangles anywhere on the terrain
+        for position in [
+            Vec2::new(0.3, -0.7),
+            Vec2::new(-1.91, 1.17),
+            Vec2::new(1.6, 0.05),
+        ] {
+            let height = heightfield.height_at(position).unwrap();
+            let expected = indices
+                .chunks_exact(3)
+                .find_map(|triangle| {
+                    let [a, b, c] = [0, 1, 2].map(|i| point(positions[triangle[i]]));
+                    let normal = (b - a).cross(c - a);
+                    let inside = [(a, b), (b, c), (c, a)].iter().all(|(start, end)| {
+                        let edge = (*end - *start).xz();
+                        edge.perp_dot(position - start.xz()) * normal.y.signum() <= 1e-6
+                    });
+                    inside.then(|| a.y - normal.xz().dot(position - a.xz()) / normal.y)
+                })
+                .unwrap();
+            assert!((height - expected).abs() < 1e-4, "{height} != {expected}");
+            let normal = heightfield.normal_at(position).unwrap();
+            assert!((normal.length() - 1.0).abs() < 1e-4 && normal.y > 0.0);
+        }
+        // Vertices keep their own height and normal
+        let corner = Vec2::new(positions[0][0], positions[0][2]);
+        assert!((heightfield.height_at(corner).unwrap() - positions[0][1]).abs() < 1e-6);
+        assert!(heightfield.height_at(Vec2::new(5.0, 0.0)).is_none());
+
+        // World positions account for the transform of the terrain entity
+        let local = Vec2::new(0.3, -0.7);
+        let transform = GlobalTransform::from(
+            Transform::from_xyz(10.0, 2.0, -5.0)
+                .with_rotation(Quat::from_rotation_y(0.5))
+                .with_scale(Vec3::splat(2.0)),
+        );
+        let world = transform.transform_point(Vec3::new(local.x, 0.0, local.y));
+        let height = heightfield.height_at_world(&transform, world.xz()).unwrap();
+        let expected = heightfield.height_at(local).unwrap().mul_add(2.0, 2.0);
+        assert!((height - expected).abs() < 1e-4, "{height} != {expected}");
+        let normal = heightfield.normal_at_world(&transform, world.xz()).unwrap();
+        let expected = transform
+            .affine()
+            .transform_vector3(heightfield.normal_at(local).unwrap());
+        assert!(normal.distance(expected.normalize()) < 1e-4);
+        assert!(heightfield.height_at(world.xz()).is_none());
+        // Tilted terrains are sampled along the vertical world line
+        let tilted = GlobalTransform::from(Transform::from_rotation(Quat::from_rotation_x(0.1)));
+        let height = heightfield.height_at_world(&tilted, local).unwrap();
+        let point = tilted
+            .affine()
+            .inverse()
+            .transform_point3(Vec3::new(local.x, height, local.y));
+        assert!((heightfield.height_at(point.xz()).unwrap() - point.y).abs() < 1e-3);
+
+        let flat = Terrain {
+            flat_shading: true,
+            ..terrain
+        }
+        .to_heightfield()
+        .unwrap();
+        let [a, b] = [Vec2::new(0.3, -0.7), Vec2::new(0.31, -0.69)];
+        assert_eq!(flat.normal_at(a), flat.normal_at(b));
+    }
+
+    #[test]
+    fn test_planet_surface() {
+        use crate::planet::Planet;
+        use bevy::{
+            math::Vec3,
+            render::mesh::{Mesh, VertexAttributeValues},
+        };
+
+        let planet = Planet::default();
+        let (_, mesh) = crate::planet::generate_mesh(&planet).expect("Generation failed");
+        let attribute = |attribute| match mesh.attribute(attribute) {
+            Some(VertexAttributeValues::Float32x3(values)) => values.clone(),
+            _ => panic!("Missing attribute"),
+        };
+        let (positions, normals) = (
+            attribute(Mesh::ATTRIBUTE_POSITION),
+            attribute(Mesh::ATTRIBUTE_NORMAL),
+        );
+        let Some(VertexAttributeValues::Float32x4(colors)) = mesh.attribute(Mesh::ATTRIBUTE_COLOR)
+        else {
+            panic!("Missing colors");
+        };
+        // Vertices sit on the surface in their direction
+        for index in [0, 37, positions.len() - 1] {
+            let position = Vec3::from(positions[index]);
+            let surface = planet.surface_at(position * 3.0).unwrap();
+            assert!(surface.position.distance(position) < 1e-5);
+            assert!((surface.radius - position.length()).abs() < 1e-5);
+            assert!(surface.normal.distance(Vec3::from(normals[index])) < 1e-4);
+            for (channel, expected) in surface.color.iter().zip(colors[index]) {
+                assert!((channel - expected).abs() < 1e-4);
+            }
+            let height = f64::from(surface.noise_value) * 100.0;
+            let region = surface.region.unwrap();
+            assert!(region == 0 || planet.regions[region].position <= height);
+            assert!(planet
+                .regions
+                .get(region + 1)
+                .is_none_or(|next| height < next.position));
+            assert!(surface.biome.is_none());
+        }
+
+        let surface = Planet {
+            biomes: Some(crate::biome::Biomes::default()),
+            ..planet
+        }
+        .surface_at(Vec3::X)
+        .unwrap();
+        assert!(surface.biome.is_some());
+    }
+
+    #[test]
+    fn test_raycast() {
+        use crate::planet::Planet;
+        use crate::terrain::Terrain;
+        use bevy::{
+            math::{Dir3, Ray3d, Vec2, Vec3},
+            transform::components::GlobalTransform,
+        };
+
+        let terrain = Terrain {
+            size: [4, 4],
+            resolution: 4,
+            ..Default::default()
+        };
+        let heightfield = terrain.to_heightfield().unwrap();
+        let position = Vec2::new(0.4, -0.3);
+        let height = heightfield.height_at(position).unwrap();
+        let hit = heightfield
+            .raycast(Ray3d::new(Vec3::new(0.4, 10.0, -0.3), Vec3::NEG_Y))
+            .unwrap();
+        assert!((hit.point.y - height).abs() < 1e-4);
+        assert!((hit.distance - (10.0 - height)).abs() < 1e-4);
+        assert_eq!(Some(hit.normal), heightfield.normal_at(position));
+
+        // World rays are cast through the transform of the terrain entity
+        let transform = GlobalTransform::from_xyz(3.0, 1.0, 0.0);
+        let ray = Ray3d::new(Vec3::new(-2.0, 6.0, 1.0), Vec3::new(1.0, -1.0, 0.0));
+        let hit = heightfield.raycast_world(&transform, ray).unwrap();
+        assert_eq!(Terrain::raycast(&heightfield, &transform, ray), Some(hit));
+        let ground = heightfield
+            .height_at_world(&transform, Vec2::new(hit.point.x, hit.point.z))
+            .unwrap();
+        assert!((hit.point.y - ground).abs() < 1e-3);
+        assert!((hit.point - ray.get_point(hit.distance)).length() < 1e-4);
+        assert!(heightfield
+            .raycast(Ray3d::new(Vec3::new(0.0, 10.0, 0.0), Vec3::Y))
+            .is_none());
+        // Nearly vertical rays only march through the band of heights
+        let ray = Ray3d::new(Vec3::new(0.4, 100.0, -0.3), Vec3::new(1e-5, -1.0, 0.0));
+        let hit = heightfield.raycast(ray).unwrap();
+        let ground = heightfield
+            .height_at(Vec2::new(hit.point.x, hit.point.z))
+            .unwrap();
+        assert!((hit.point.y - ground).abs() < 1e-3);
+        assert!(heightfield.min_height <= height && height <= heightfield.max_height);
+        let flat = crate::terrain::TerrainHeightfield {
+            heights: vec![0.5; heightfield.heights.len()],
+            min_height: 0.5,
+            max_height: 0.5,
+            ..heightfield
+        };
+        let hit = flat
+            .raycast(Ray3d::new(Vec3::new(0.4, 10.0, -0.3), Vec3::NEG_Y))
+            .unwrap();
+        assert!((hit.point.y - 0.5).abs() < 1e-4);
+
+        let planet = Planet::default();
+        let sampler = planet.sampler().unwrap();
+        let surface = sampler.surface_at(Vec3::Z);
+        let hit = sampler
+            .raycast(Ray3d {
+                origin: Vec3::new(0.0, 0.0, 5.0),
+                direction: Dir3::NEG_Z,
+            })
+            .unwrap();
+        assert!((hit.distance - (5.0 - surface.radius)).abs() < 1e-4);
+        assert!(hit.normal.distance(surface.normal) < 1e-2);
+        assert!(sampler
+            .raycast(Ray3d::new(Vec3::new(0.0, 0.0, 5.0), Vec3::X))
+            .is_none());
+        let ray = Ray3d::new(Vec3::new(0.3, 0.2, 5.0), Vec3::NEG_Z);
+        assert_eq!(planet.raycast(ray).unwrap(), sampler.raycast(ray));
+        assert!(planet.raycast(ray).unwrap().is_some());
+        // Rays starting inside the surface do not hit it
+        assert!(sampler.raycast(Ray3d::new(Vec3::ZERO, Vec3::X)).is_none());
+    }
+
+    #[test]
+    fn test_generation_task_failure() {
+        use crate::util::GenerationTask;
+        use bevy::tasks::{AsyncComputeTaskPool, TaskPool};
+
+        AsyncComputeTaskPool::get_or_init(TaskPool::default);
+        let mut task = GenerationTask::<()>::spawn(|| panic!("Generation bug"));
+        let result = loop {
+            if let Some(result) = task.poll() {
+                break result;
+            }
+            std::thread::yield_now();
+        };
+        assert_eq!(result, Err("Generation panicked".to_string()));
+    }
 }
@@ -1,47 +1,33 @@
 // Adapted from https://github.com/gltf-rs/gltf/blob/main/examples/export/main.rs
 
+use base64::{engine::general_purpose::STANDARD, Engine};
 use gltf::json;
-#[cfg(not(target_arch = "wasm32"))]
-use rfd::FileDialog;
-use wasm_bindgen::prelude::wasm_bindgen;
 
-use std::{fs, mem};
+use super::Model;
 
 use gltf::json::validation::Checked::Valid;
 use json::validation::USize64;
 use std::borrow::Cow;
-use std::io::Write;
-
-#[wasm_bindgen(module = "/src/util/save.js")]
-extern "C" {
-    fn save(data: &[u8], filename: &str, r#type: &str);
-}
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
-pub enum Output {
-    /// Output standard glTF.
-    Standard,
+pub enum Output<'a> {
+    /// Output standard glTF, referencing the buffer by the given uri.
+    Standard(&'a str),
+    /// Output standard glTF, with the buffer embedded as a data uri.
+    Embedded,
     /// Output binary glTF.
     Binary,
 }
 
-#[derive(Copy, Clone, Debug)]
-#[repr(C)]
-pub struct Vertex {
-    pub position: [f32; 3],
-    pub color: [f32; 3],
-}
-
 /// Calculate bounding coordinates of a list of vertices, used for the clipping distance of the model
-fn bounding_coords(points: &[Vertex]) -> ([f32; 3], [f32; 3]) {
+fn bounding_coords(points: &[[f32; 3]]) -> ([f32; 3], [f32; 3]) {
     let mut min = [f32::MAX, f32::MAX, f32::MAX];
     let mut max = [f32::MIN, f32::MIN, f32::MIN];
 
     for point in points {
-        let p = point.position;
         for i in 0..3 {
-            min[i] = f32::min(min[i], p[i]);
-            max[i] = f32::max(max[i], p[i]);
+            min[i] = f32::min(min[i], point[i]);
+            max[i] = f32::max(max[i], point[i]);
         }
     }
     (min, max)
@@ -51,164 +37,281 @@ fn align_to_multiple_of_four(n: &mut usize) {
     *n = (*n + 3) & !3;
 }
 
-fn to_padded_byte_vector<T>(vec: Vec<T>) -> Vec<u8> {
-    let byte_length = vec.len() * mem::size_of::<T>();
-    let byte_capacity = vec.capacity() * mem::size_of::<T>();
-    let alloc = vec.into_boxed_slice();
-    let ptr = Box::<[T]>::into_raw(alloc).cast::<u8>();
-    let mut new_vec = unsafe { Vec::from_raw_parts(ptr, byte_length, byte_capacity) };
-    while new_vec.len() % 4 != 0 {
-        new_vec.push(0); // pad to multiple of four bytes
-    }
-    new_vec
+/// Little endian bytes of vectors of `f32`
+fn f32_bytes<const N: usize>(values: &[[f32; N]]) -> Vec<u8> {
+    values
+        .iter()
+        .flatten()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
 }
 
-pub fn export_gltf(output: Output, vertices: Vec<Vertex>) {
-    let (min, max) = bounding_coords(&vertices);
+/// Pads `bin` with zeros to a multiple of four bytes
+fn pad(bin: &mut Vec<u8>) {
+    let mut length = bin.len();
+    align_to_multiple_of_four(&mut length);
+    bin.resize(length, 0);
+}
 
-    let buffer_length = vertices.len() * mem::size_of::<Vertex>();
-    let buffer = json::Buffer {
-        byte_length: USize64::from(buffer_length),
-        extensions: Option::default(),
-        extras: Default::default(),
-        name: None,
-        uri: if output == Output::Standard {
-            Some("buffer0.bin".into())
-        } else {
-            None
-        },
-    };
-    let buffer_view = json::buffer::View {
+/// Appends `bytes` to `bin`, aligned to four bytes, and adds a buffer view over them
+fn push_view(
+    root: &mut json::Root,
+    bin: &mut Vec<u8>,
+    bytes: &[u8],
+    target: Option<json::buffer::Target>,
+) -> json::Index<json::buffer::View> {
+    pad(bin);
+    let byte_offset = bin.len();
+    let byte_length = bytes.len();
+    bin.extend_from_slice(bytes);
+    root.push(json::buffer::View {
         buffer: json::Index::new(0),
-        byte_length: buffer.byte_length,
-        byte_offset: None,
-        byte_stride: Some(json::buffer::Stride(mem::size_of::<Vertex>())),
+        byte_length: USize64::from(byte_length),
+        byte_offset: Some(USize64::from(byte_offset)),
+        byte_stride: None,
         extensions: Option::default(),
         extras: Default::default(),
         name: None,
-        target: Some(Valid(json::buffer::Target::ArrayBuffer)),
-    };
-    let positions = json::Accessor {
-        buffer_view: Some(json::Index::new(0)),
+        target: target.map(Valid),
+    })
+}
+
+fn push_accessor(
+    root: &mut json::Root,
+    buffer_view: json::Index<json::buffer::View>,
+    count: usize,
+    component_type: json::accessor::ComponentType,
+    type_: json::accessor::Type,
+    bounds: Option<([f32; 3], [f32; 3])>,
+) -> json::Index<json::Accessor> {
+    root.push(json::Accessor {
+        buffer_view: Some(buffer_view),
         byte_offset: Some(USize64(0)),
-        count: USize64::from(vertices.len()),
-        component_type: Valid(json::accessor::GenericComponentType(
-            json::accessor::ComponentType::F32,
-        )),
+        count: USize64::from(count),
+        component_type: Valid(json::accessor::GenericComponentType(component_type)),
         extensions: Option::default(),
         extras: Default::default(),
-        type_: Valid(json::accessor::Type::Vec3),
-        min: Some(json::Value::from(Vec::from(min))),
-        max: Some(json::Value::from(Vec::from(max))),
-        name: None,
-        normalized: false,
-        sparse: None,
-    };
-    let colors = json::Accessor {
-        buffer_view: Some(json::Index::new(0)),
-        byte_offset: Some(USize64::from(3 * mem::size_of::<f32>())),
-        count: USize64::from(vertices.len()),
-        component_type: Valid(json::accessor::GenericComponentType(
-            json::accessor::ComponentType::F32,
-        )),
-        extensions: Option::default(),
-        extras: Default::default(),
-        type_: Valid(json::accessor::Type::Vec3),
-        min: None,
-        max: None,
+        type_: Valid(type_),
+        min: bounds.map(|(min, _)| json::Value::from(Vec::from(min))),
+        max: bounds.map(|(_, max)| json::Value::from(Vec::from(max))),
         name: None,
         normalized: false,
         sparse: None,
+    })
+}
+
+/// Builds the glTF document for `model`, returning it with the contents of its only buffer
+fn build_root(output: Output, model: &Model) -> (json::Root, Vec<u8>) {
+    use json::accessor::{ComponentType, Type};
+    use json::buffer::Target;
+
+    let mut root = json::Root::default();
+    let mut bin = vec![];
+    let count = model.positions.len();
+
+    let view = push_view(
+        &mut root,
+        &mut bin,
+        &f32_bytes(model.positions),
+        Some(Target::ArrayBuffer),
+    );
+    let positions = push_accessor(
+        &mut root,
+        view,
+        count,
+        ComponentType::F32,
+        Type::Vec3,
+        Some(bounding_coords(model.positions)),
+    );
+    let view = push_view(
+        &mut root,
+        &mut bin,
+        &f32_bytes(model.normals),
+        Some(Target::ArrayBuffer),
+    );
+    let normals = push_accessor(&mut root, view, count, ComponentType::F32, Type::Vec3, None);
+    let view = push_view(
+        &mut root,
+        &mut bin,
+        &f32_bytes(model.uvs),
+        Some(Target::ArrayBuffer),
+    );
+    let uvs = push_accessor(&mut root, view, count, ComponentType::F32, Type::Vec2, None);
+    // glTF multiplies vertex colors with the base color texture
+    let colors = if model.texture.is_none() {
+        let view = push_view(
+            &mut root,
+            &mut bin,
+            &f32_bytes(model.colors),
+            Some(Target::ArrayBuffer),
+        );
+        Some(push_accessor(
+            &mut root,
+            view,
+            count,
+            ComponentType::F32,
+            Type::Vec4,
+            None,
+        ))
+    } else {
+        None
     };
+    let view = push_view(
+        &mut root,
+        &mut bin,
+        &model
+            .indices
+            .iter()
+            .flat_map(|index| index.to_le_bytes())
+            .collect::<Vec<u8>>(),
+        Some(Target::ElementArrayBuffer),
+    );
+    let indices = push_accessor(
+        &mut root,
+        view,
+        model.indices.len(),
+        ComponentType::U32,
+        Type::Scalar,
+        None,
+    );
+
+    let base_color_texture = model.texture.map(|png| {
+        let view = push_view(&mut root, &mut bin, png, None);
+        let image = root.push(json::Image {
+            buffer_view: Some(view),
+            mime_type: Some(json::image::MimeType("image/png".into())),
+            name: None,
+            uri: None,
+            extensions: Option::default(),
+            extras: Default::default(),
+        });
+        let sampler = root.push(json::texture::Sampler {
+            mag_filter: Some(Valid(json::texture::MagFilter::Linear)),
+            min_filter: Some(Valid(json::texture::MinFilter::LinearMipmapLinear)),
+            wrap_s: Valid(json::texture::WrappingMode::ClampToEdge),
+            wrap_t: Valid(json::texture::WrappingMode::ClampToEdge),
+            ..Default::default()
+        });
+        let texture = root.push(json::Texture {
+            name: None,
+            sampler: Some(sampler),
+            source: image,
+            extensions: Option::default(),
+            extras: Default::default(),
+        });
+        json::texture::Info {
+            index: texture,
+            tex_coord: 0,
+            extensions: Option::default(),
+            extras: Default::default(),
+        }
+    });
+
+    let material = root.push(json::Material {
+        pbr_metallic_roughness: json::material::PbrMetallicRoughness {
+            base_color_texture,
+            metallic_factor: json::material::StrengthFactor(0.0),
+            roughness_factor: json::material::StrengthFactor(0.5),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
 
     let primitive = json::mesh::Primitive {
         attributes: {
             let mut map = std::collections::BTreeMap::new();
-            map.insert(Valid(json::mesh::Semantic::Positions), json::Index::new(0));
-            map.insert(Valid(json::mesh::Semantic::Colors(0)), json::Index::new(1));
+            map.insert(Valid(json::mesh::Semantic::Positions), positions);
+            map.insert(Valid(json::mesh::Semantic::Normals), normals);
+            map.insert(Valid(json::mesh::Semantic::TexCoords(0)), uvs);
+            if let Some(colors) = colors {
+                map.insert(Valid(json::mesh::Semantic::Colors(0)), colors);
+            }
             map
         },
         extensions: Option::default(),
         extras: Default::default(),
-        indices: None,
-        material: None,
-        mode: Valid(json::mesh::Mode::Triangles),
+        indices: Some(indices),
+        material: Some(material),
+        mode: Valid(if model.lines {
+            json::mesh::Mode::Lines
+        } else {
+            json::mesh::Mode::Triangles
+        }),
         targets: None,
     };
 
-    let mesh = json::Mesh {
+    let mesh = root.push(json::Mesh {
         extensions: Option::default(),
         extras: Default::default(),
         name: None,
         primitives: vec![primitive],
         weights: None,
-    };
+    });
 
-    let node = json::Node {
-        camera: None,
-        children: None,
+    let node = root.push(json::Node {
+        mesh: Some(mesh),
+        ..Default::default()
+    });
+
+    let scene = root.push(json::Scene {
         extensions: Option::default(),
         extras: Default::default(),
-        matrix: None,
-        mesh: Some(json::Index::new(0)),
         name: None,
-        rotation: None,
-        scale: None,
-        translation: None,
-        skin: None,
-        weights: None,
-    };
-
-    let root = json::Root {
-        accessors: vec![positions, colors],
-        buffers: vec![buffer],
-        buffer_views: vec![buffer_view],
-        meshes: vec![mesh],
         nodes: vec![node],
-        scenes: vec![json::Scene {
-            extensions: Option::default(),
-            extras: Default::default(),
-            name: None,
-            nodes: vec![json::Index::new(0)],
-        }],
-        ..json::Root::default()
-    };
+    });
+    root.scene = Some(scene);
 
-    match output {
-        Output::Standard => {
-            let _ = fs::create_dir("triangle");
+    pad(&mut bin);
+    root.push(json::Buffer {
+        byte_length: USize64::from(bin.len()),
+        extensions: Option::default(),
+        extras: Default::default(),
+        name: None,
+        uri: match output {
+            Output::Standard(uri) => Some(uri.into()),
+            Output::Embedded => Some(format!(
+                "data:application/octet-stream;base64,{}",
+                STANDARD.encode(&bin)
+            )),
+            Output::Binary => None,
+        },
+    });
+    (root, bin)
+}
 
-            let writer = fs::File::create("triangle/triangle.gltf").expect("I/O error");
-            json::serialize::to_writer_pretty(writer, &root).expect("Serialization error");
+/// Encodes `model`, returning the main file along with the buffer when it is stored separately
+pub fn export_gltf(output: Output, model: &Model) -> Result<(Vec<u8>, Option<Vec<u8>>), String> {
+    let (root, bin) = build_root(output, model);
 
-            let bin = to_padded_byte_vector(vertices);
-            let mut writer = fs::File::create("triangle/buffer0.bin").expect("I/O error");
-            writer.write_all(&bin).expect("I/O error");
+    match output {
+        Output::Standard(_) => {
+            let json_string = json::serialize::to_string_pretty(&root)
+                .map_err(|error| format!("glTF serialization failed: {error}"))?;
+            Ok((json_string.into_bytes(), Some(bin)))
+        }
+        Output::Embedded => {
+            let json_string = json::serialize::to_string_pretty(&root)
+                .map_err(|error| format!("glTF serialization failed: {error}"))?;
+            Ok((json_string.into_bytes(), None))
         }
         Output::Binary => {
-            let json_string = json::serialize::to_string(&root).expect("Serialization error");
-            let mut json_offset = json_string.len() as usize;
+            let json_string = json::serialize::to_string(&root)
+                .map_err(|error| format!("glTF serialization failed: {error}"))?;
+            let mut json_offset = json_string.len();
             align_to_multiple_of_four(&mut json_offset);
             let glb = gltf::binary::Glb {
                 header: gltf::binary::Header {
                     magic: *b"glTF",
                     version: 2,
-                    length: (json_offset + buffer_length) as u32, // This may truncate long buffers
+                    length: (json_offset + bin.len()) as u32, // This may truncate long buffers
                 },
-                bin: Some(Cow::Owned(to_padded_byte_vector(vertices))),
+                bin: Some(Cow::Owned(bin)),
                 json: Cow::Owned(json_string.into_bytes()),
             };
-
-            #[cfg(target_arch = "wasm32")]
-            {
-                let buffer = glb.to_vec().expect("glTF binary output error");
-                save(&buffer, "model.glb", "model/gtlf-binary");
-            }
-            #[cfg(not(target_arch = "wasm32"))]
-            if let Some(file_path) = FileDialog::new().save_file() {
-                let writer = std::fs::File::create(file_path).expect("I/O error");
-                glb.to_writer(writer).expect("glTF binary output error");
-            }
+            let glb = glb
+                .to_vec()
+                .map_err(|error| format!("glTF binary output failed: {error}"))?;
+            Ok((glb, None))
         }
     }
 }
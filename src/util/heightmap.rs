@@ -0,0 +1,49 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageBuffer, ImageFormat, Luma};
+use tiff::encoder::{colortype::Gray32Float, TiffEncoder};
+
+use crate::export::HeightmapFormat;
+
+/// Encodes a noise map, with values in the range [0, 100], as a grayscale heightmap.
+/// Rows of the heightmap follow the second axis of the noise map, as in the generated images
+pub fn export_heightmap(
+    noise_values: &[Vec<f64>],
+    format: HeightmapFormat,
+) -> Result<Vec<u8>, String> {
+    let width = noise_values.len();
+    let height = noise_values.first().map_or(0, Vec::len);
+    let heights: Vec<f32> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (noise_values[x][y] / 100.0).clamp(0.0, 1.0) as f32))
+        .collect();
+    let to_u16 = |height: f32| (height * f32::from(u16::MAX)).round() as u16;
+
+    match format {
+        HeightmapFormat::Png16 => {
+            let buffer: ImageBuffer<Luma<u16>, Vec<u16>> = ImageBuffer::from_raw(
+                width as u32,
+                height as u32,
+                heights.iter().map(|&height| to_u16(height)).collect(),
+            )
+            .ok_or("Invalid heightmap size")?;
+            let mut png = Cursor::new(vec![]);
+            DynamicImage::ImageLuma16(buffer)
+                .write_to(&mut png, ImageFormat::Png)
+                .map_err(|error| format!("PNG encoding failed: {error}"))?;
+            Ok(png.into_inner())
+        }
+        HeightmapFormat::Tiff32 => {
+            let mut tiff = Cursor::new(vec![]);
+            TiffEncoder::new(&mut tiff)
+                .and_then(|mut encoder| {
+                    encoder.write_image::<Gray32Float>(width as u32, height as u32, &heights)
+                })
+                .map_err(|error| format!("TIFF encoding failed: {error}"))?;
+            Ok(tiff.into_inner())
+        }
+        HeightmapFormat::R16 => Ok(heights
+            .iter()
+            .flat_map(|&height| to_u16(height).to_le_bytes())
+            .collect()),
+    }
+}
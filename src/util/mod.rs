@@ -1,8 +1,39 @@
 mod gltf;
-use gltf::{export_gltf, Output, Vertex};
+mod heightmap;
+mod obj;
+mod ply;
+mod stl;
+#[cfg(target_arch = "wasm32")]
+use std::sync::{Arc, Mutex};
+use std::{
+    fs,
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::Path,
+};
+
 #[cfg(not(target_arch = "wasm32"))]
-use image::save_buffer;
-use image::{codecs::png::PngEncoder, DynamicImage, ImageBuffer, ImageEncoder, Rgba};
+use bevy::tasks::{block_on, poll_once, Task};
+use bevy::{
+    asset::{Asset, Assets, Handle},
+    log::error,
+    render::{
+        mesh::{Indices, Mesh, VertexAttributeValues},
+        render_asset::RenderAssetUsages,
+        render_resource::{PrimitiveTopology, TextureFormat},
+        texture::Image,
+    },
+    tasks::AsyncComputeTaskPool,
+};
+use gltf::{export_gltf, Output};
+pub use heightmap::export_heightmap;
+use obj::export_obj;
+use ply::export_ply;
+use stl::export_stl;
+
+use crate::export::ModelFormat;
+use image::{
+    codecs::png::PngEncoder, ExtendedColorType, ImageBuffer, ImageEncoder, Rgba, RgbaImage,
+};
 #[cfg(not(target_arch = "wasm32"))]
 use rfd::FileDialog;
 #[cfg(target_arch = "wasm32")]
@@ -14,49 +45,245 @@ extern "C" {
     fn save(data: &[u8], filename: &str, r#type: &str);
 }
 
-pub fn export_asset(image_buffer: ImageBuffer<Rgba<u8>, Vec<u8>>) {
+/// Replaces the asset behind `handle` in place, so that regenerating does not accumulate assets.
+/// Weak handles (such as the default handle) are never overwritten, a new asset is added instead
+pub fn replace_asset<A: Asset>(assets: &mut Assets<A>, handle: &mut Handle<A>, asset: A) {
+    match assets.get_mut(&*handle) {
+        Some(existing) if matches!(handle, Handle::Strong(_)) => *existing = asset,
+        _ => *handle = assets.add(asset),
+    }
+}
+
+/// Generation running on the `AsyncComputeTaskPool`.
+/// Dropping it, such as when a newer configuration replaces it, cancels the generation
+pub struct GenerationTask<T> {
+    #[cfg(not(target_arch = "wasm32"))]
+    task: Task<Result<T, String>>,
+    /// The wasm task pool does not return its tasks, so the result is written to a shared slot
+    #[cfg(target_arch = "wasm32")]
+    slot: Arc<Mutex<Option<Result<T, String>>>>,
+}
+
+impl<T: Send + 'static> GenerationTask<T> {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn spawn(generate: impl FnOnce() -> Result<T, String> + Send + 'static) -> Self {
+        Self {
+            task: AsyncComputeTaskPool::get().spawn(async move { run(generate) }),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn spawn(generate: impl FnOnce() -> Result<T, String> + Send + 'static) -> Self {
+        let slot = Arc::new(Mutex::new(None));
+        let result = Arc::clone(&slot);
+        AsyncComputeTaskPool::get().spawn(async move {
+            // Skip generations replaced before they started
+            if Arc::strong_count(&result) > 1 {
+                let output = run(generate);
+                if let Ok(mut result) = result.lock() {
+                    *result = Some(output);
+                }
+            }
+        });
+        Self { slot }
+    }
+
+    /// Returns the result once the generation is finished.
+    /// A task cancelled by the task pool results in an error
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn poll(&mut self) -> Option<Result<T, String>> {
+        catch_unwind(AssertUnwindSafe(|| block_on(poll_once(&mut self.task))))
+            .unwrap_or_else(|_| Some(Err("Generation was cancelled".to_string())))
+    }
+
+    /// Returns the result once the generation is finished
+    #[cfg(target_arch = "wasm32")]
+    pub fn poll(&mut self) -> Option<Result<T, String>> {
+        self.slot.lock().ok()?.take()
+    }
+}
+
+/// Runs `generate`, turning a panic into an error
+fn run<T>(generate: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    catch_unwind(AssertUnwindSafe(generate))
+        .unwrap_or_else(|_| Err("Generation panicked".to_string()))
+}
+
+pub fn image_from_buffer(image_buffer: ImageBuffer<Rgba<u8>, Vec<u8>>) -> Image {
+    Image::from_dynamic(image_buffer.into(), true, RenderAssetUsages::RENDER_WORLD)
+        .convert(TextureFormat::Rgba8UnormSrgb)
+        .expect("Could not convert to Rgba8UnormSrgb")
+}
+
+/// Creates an image that is not color corrected, as needed for data such as normal maps
+pub fn linear_image_from_buffer(image_buffer: ImageBuffer<Rgba<u8>, Vec<u8>>) -> Image {
+    Image::from_dynamic(image_buffer.into(), false, RenderAssetUsages::RENDER_WORLD)
+}
+
+pub fn png_bytes(image_buffer: &RgbaImage) -> Result<Vec<u8>, String> {
+    let mut png_buffer: Vec<u8> = vec![];
+    PngEncoder::new(&mut png_buffer)
+        .write_image(
+            image_buffer,
+            image_buffer.width(),
+            image_buffer.height(),
+            ExtendedColorType::Rgba8,
+        )
+        .map_err(|error| format!("PNG encoding failed: {error}"))?;
+    Ok(png_buffer)
+}
+
+/// Writes `bytes` to `path`, creating missing parent directories
+pub fn write_bytes(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
     {
-        let mut png_buffer: Vec<u8> = vec![];
-        let png_encoder = PngEncoder::new(&mut png_buffer);
-        let color_type = DynamicImage::from(image_buffer.clone()).color();
-        png_encoder
-            .write_image(
-                &image_buffer,
-                image_buffer.width(),
-                image_buffer.height(),
-                color_type.into(),
-            )
-            .expect("Failed to write to png");
-
-        #[cfg(target_arch = "wasm32")]
-        save(&png_buffer, "asset.png", "image/png");
+        fs::create_dir_all(parent)
+            .map_err(|error| format!("Could not create {}: {error}", parent.display()))?;
     }
+    fs::write(path, bytes).map_err(|error| format!("Could not write {}: {error}", path.display()))
+}
+
+/// Lets the user save `bytes`, using a file dialog on native and a download on wasm
+pub fn save_bytes(bytes: &[u8], filename: &str, mime_type: &str) {
+    #[cfg(target_arch = "wasm32")]
+    save(bytes, filename, mime_type);
     #[cfg(not(target_arch = "wasm32"))]
     {
-        if let Some(file_path) = FileDialog::new().save_file() {
-            let _ = save_buffer(
-                file_path,
-                &image_buffer.clone(),
-                image_buffer.width(),
-                image_buffer.height(),
-                DynamicImage::from(image_buffer).color(),
-            );
+        let _ = mime_type;
+        if let Some(file_path) = FileDialog::new().set_file_name(filename).save_file() {
+            if let Err(error) = write_bytes(&file_path, bytes) {
+                error!("{error}");
+            }
         }
     }
 }
 
-pub fn export_model(positions: &[[f32; 3]], indices: Vec<u32>, colors: &[[f32; 4]]) {
-    let mut vertices: Vec<Vertex> = vec![];
+pub fn export_asset(image_buffer: &RgbaImage, filename: &str) {
+    match png_bytes(image_buffer) {
+        Ok(png_buffer) => save_bytes(&png_buffer, filename, "image/png"),
+        Err(error) => error!("{error}"),
+    }
+}
+
+/// Indexed mesh data written by the model exporters
+pub struct Model<'a> {
+    pub positions: &'a [[f32; 3]],
+    pub normals: &'a [[f32; 3]],
+    pub uvs: &'a [[f32; 2]],
+    pub colors: &'a [[f32; 4]],
+    pub indices: &'a [u32],
+    /// If true, indices describe a line list instead of a triangle list
+    pub lines: bool,
+    /// PNG encoded base color texture
+    pub texture: Option<&'a [u8]>,
+}
 
-    for i in indices {
-        vertices.push(Vertex {
-            position: positions[i as usize],
-            color: [
-                colors[i as usize][0],
-                colors[i as usize][1],
-                colors[i as usize][2],
-            ],
-        });
+/// Encodes `mesh` in `format`, returning the files to write, named after `name`
+pub fn encode_model(
+    mesh: &Mesh,
+    texture: Option<&RgbaImage>,
+    format: ModelFormat,
+    name: &str,
+) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return Err("Mesh has no positions".to_string());
+    };
+    let Some(VertexAttributeValues::Float32x3(normals)) = mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+    else {
+        return Err("Mesh has no normals".to_string());
+    };
+    let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0) else {
+        return Err("Mesh has no uvs".to_string());
+    };
+    let Some(VertexAttributeValues::Float32x4(colors)) = mesh.attribute(Mesh::ATTRIBUTE_COLOR)
+    else {
+        return Err("Mesh has no colors".to_string());
+    };
+    let indices = match mesh.indices() {
+        Some(Indices::U32(indices)) => indices.clone(),
+        Some(Indices::U16(indices)) => indices.iter().map(|&index| u32::from(index)).collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    let texture = texture.map(png_bytes).transpose()?;
+    let model = Model {
+        positions,
+        normals,
+        uvs,
+        colors,
+        indices: &indices,
+        lines: mesh.primitive_topology() == PrimitiveTopology::LineList,
+        texture: texture.as_deref(),
+    };
+    let file_name = format!("{name}.{}", format.extension());
+    let bin_name = format!("{name}.bin");
+    let output = match format {
+        ModelFormat::Glb => Output::Binary,
+        ModelFormat::Gltf => Output::Standard(&bin_name),
+        ModelFormat::GltfEmbedded => Output::Embedded,
+        ModelFormat::Obj => return Ok(export_obj(&model, name)),
+        ModelFormat::Ply => return Ok(vec![(file_name, export_ply(&model))]),
+        ModelFormat::Stl => return Ok(vec![(file_name, export_stl(&model)?)]),
+    };
+    let (main, bin) = export_gltf(output, &model)?;
+    let mut files = vec![(file_name, main)];
+    files.extend(bin.map(|bin| (bin_name, bin)));
+    Ok(files)
+}
+
+pub fn mesh_to_glb(mesh: &Mesh, texture: Option<&RgbaImage>) -> Result<Vec<u8>, String> {
+    let mut files = encode_model(mesh, texture, ModelFormat::Glb, "model")?;
+    Ok(files.remove(0).1)
+}
+
+/// Writes `mesh` in `format` next to `path`, naming the files after the file stem of `path`
+pub fn write_model(
+    mesh: &Mesh,
+    texture: Option<&RgbaImage>,
+    format: ModelFormat,
+    path: &Path,
+) -> Result<(), String> {
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| format!("Invalid file name: {}", path.display()))?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    for (file_name, bytes) in encode_model(mesh, texture, format, name)? {
+        write_bytes(&directory.join(file_name), &bytes)?;
+    }
+    Ok(())
+}
+
+/// Lets the user save `mesh` in `format`, `name` is used as the default file name
+pub fn export_model(mesh: &Mesh, texture: Option<&RgbaImage>, format: ModelFormat, name: &str) {
+    #[cfg(target_arch = "wasm32")]
+    match encode_model(mesh, texture, format, name) {
+        Ok(files) => {
+            for (file_name, bytes) in files {
+                let mime_type = match Path::new(&file_name).extension().and_then(|e| e.to_str()) {
+                    Some("glb") => "model/gltf-binary",
+                    Some("gltf") => "model/gltf+json",
+                    Some("obj") => "model/obj",
+                    Some("mtl") => "model/mtl",
+                    Some("stl") => "model/stl",
+                    Some("png") => "image/png",
+                    _ => "application/octet-stream",
+                };
+                save(&bytes, &file_name, mime_type);
+            }
+        }
+        Err(error) => error!("{error}"),
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = FileDialog::new()
+        .set_file_name(format!("{name}.{}", format.extension()))
+        .save_file()
+    {
+        if let Err(error) = write_model(mesh, texture, format, &path) {
+            error!("{error}");
+        }
     }
-    export_gltf(Output::Binary, vertices);
 }
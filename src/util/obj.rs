@@ -0,0 +1,60 @@
+use std::fmt::Write;
+
+use super::Model;
+
+/// Encodes `model` as Wavefront OBJ, returning the `.obj` and `.mtl` files named after `name`,
+/// along with the `.png` texture if the model has one.
+/// Vertex colors are written after the position of each vertex, as most importers expect
+pub fn export_obj(model: &Model, name: &str) -> Vec<(String, Vec<u8>)> {
+    let mut obj = String::new();
+    let _ = writeln!(obj, "mtllib {name}.mtl");
+    let _ = writeln!(obj, "o {name}");
+    for (position, color) in model.positions.iter().zip(model.colors) {
+        let _ = writeln!(
+            obj,
+            "v {} {} {} {} {} {}",
+            position[0], position[1], position[2], color[0], color[1], color[2]
+        );
+    }
+    // OBJ texture coordinates start at the bottom of the image
+    for uv in model.uvs {
+        let _ = writeln!(obj, "vt {} {}", uv[0], 1.0 - uv[1]);
+    }
+    for normal in model.normals {
+        let _ = writeln!(obj, "vn {} {} {}", normal[0], normal[1], normal[2]);
+    }
+    let _ = writeln!(obj, "usemtl {name}");
+    if model.lines {
+        for line in model.indices.chunks_exact(2) {
+            let _ = writeln!(
+                obj,
+                "l {}/{} {}/{}",
+                line[0] + 1,
+                line[0] + 1,
+                line[1] + 1,
+                line[1] + 1
+            );
+        }
+    } else {
+        for triangle in model.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+            let _ = writeln!(obj, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}");
+        }
+    }
+
+    let mut mtl = String::new();
+    let _ = writeln!(mtl, "newmtl {name}");
+    let _ = writeln!(mtl, "Ka 0 0 0");
+    let _ = writeln!(mtl, "Kd 1 1 1");
+    let _ = writeln!(mtl, "Ks 0 0 0");
+    let _ = writeln!(mtl, "illum 1");
+
+    let mut files = vec![];
+    if let Some(texture) = model.texture {
+        let _ = writeln!(mtl, "map_Kd {name}.png");
+        files.push((format!("{name}.png"), texture.to_vec()));
+    }
+    files.insert(0, (format!("{name}.mtl"), mtl.into_bytes()));
+    files.insert(0, (format!("{name}.obj"), obj.into_bytes()));
+    files
+}
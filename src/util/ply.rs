@@ -0,0 +1,59 @@
+use super::Model;
+
+/// Encodes `model` as binary little endian PLY with vertex normals and colors.
+/// Wireframe models are written as edges instead of faces
+pub fn export_ply(model: &Model) -> Vec<u8> {
+    let (element, property, count) = if model.lines {
+        (
+            "edge",
+            "property int vertex1\nproperty int vertex2",
+            model.indices.len() / 2,
+        )
+    } else {
+        (
+            "face",
+            "property list uchar int vertex_indices",
+            model.indices.len() / 3,
+        )
+    };
+    let header = format!(
+        "ply\n\
+         format binary_little_endian 1.0\n\
+         element vertex {}\n\
+         property float x\n\
+         property float y\n\
+         property float z\n\
+         property float nx\n\
+         property float ny\n\
+         property float nz\n\
+         property uchar red\n\
+         property uchar green\n\
+         property uchar blue\n\
+         property uchar alpha\n\
+         element {element} {count}\n\
+         {property}\n\
+         end_header\n",
+        model.positions.len()
+    );
+
+    let mut ply = header.into_bytes();
+    for ((position, normal), color) in model.positions.iter().zip(model.normals).zip(model.colors) {
+        for value in position.iter().chain(normal) {
+            ply.extend(value.to_le_bytes());
+        }
+        ply.extend(color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8));
+    }
+    if model.lines {
+        for index in model.indices {
+            ply.extend(index.to_le_bytes());
+        }
+    } else {
+        for triangle in model.indices.chunks_exact(3) {
+            ply.push(3);
+            for index in triangle {
+                ply.extend(index.to_le_bytes());
+            }
+        }
+    }
+    ply
+}
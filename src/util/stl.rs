@@ -0,0 +1,28 @@
+use bevy::math::Vec3;
+
+use super::Model;
+
+/// Encodes `model` as binary STL, with a face normal per triangle
+pub fn export_stl(model: &Model) -> Result<Vec<u8>, String> {
+    if model.lines {
+        return Err("STL does not support wireframe meshes".to_string());
+    }
+    let triangles = model.indices.len() / 3;
+    let triangle_count =
+        u32::try_from(triangles).map_err(|_| "Too many triangles for STL".to_string())?;
+
+    let mut stl = Vec::with_capacity(84 + triangles * 50);
+    stl.extend([0; 80]);
+    stl.extend(triangle_count.to_le_bytes());
+    for triangle in model.indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]]
+            .map(|index| Vec3::from(model.positions[index as usize]));
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+        for value in [normal, a, b, c].iter().flat_map(Vec3::to_array) {
+            stl.extend(value.to_le_bytes());
+        }
+        // Attribute byte count
+        stl.extend([0; 2]);
+    }
+    Ok(stl)
+}